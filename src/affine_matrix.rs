@@ -1,7 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::{f32::consts::PI, ops::Mul};
 
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct AffineMatrix {
     pub matrix: [[f32; 4]; 4],
 }
@@ -160,6 +161,16 @@ impl AffineMatrix {
         *self = phi_rotation * theta_rotation * y_rotation;
     }
 
+    /// A rotation turning the seed by `spin` about its stem, then pointing the stem along
+    /// `direction`.
+    pub fn pointing(direction: [f32; 3], spin: f32) -> Self {
+        let mut spin_matrix = AffineMatrix::new();
+        spin_matrix.set_rotate_y(spin);
+        let mut rotation = AffineMatrix::new();
+        rotation.rotate_towards([0.0; 3], direction);
+        spin_matrix * rotation
+    }
+
     pub fn combine(&mut self, other: Self, alpha: f32) {
         for i in 0..4 {
             for j in 0..4 {
@@ -191,6 +202,18 @@ impl AffineMatrix {
         result
     }
 
+    /// Applies the matrix to a point the same way the shaders do, with the translation
+    /// in the last row.
+    pub fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let mut result = [self.matrix[3][0], self.matrix[3][1], self.matrix[3][2]];
+        for (j, out) in result.iter_mut().enumerate() {
+            for (i, p) in point.iter().enumerate() {
+                *out += self.matrix[i][j] * p;
+            }
+        }
+        result
+    }
+
     pub fn multiply_4d(&self, point: [f32; 4]) -> [f32; 4] {
         let mut result = [0.0; 4];
         for i in 0..4 {
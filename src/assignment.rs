@@ -0,0 +1,123 @@
+/// Solves the assignment problem with the Hungarian method: returns, for every row, the
+/// column it is given, so that no column is used twice and the summed cost is as small as
+/// possible. There may be more columns than rows, but not fewer.
+pub fn solve(cost: &[Vec<f32>]) -> Vec<usize> {
+    let rows = cost.len();
+    if rows == 0 {
+        return Vec::new();
+    }
+    let n = cost[0].len();
+    assert!(cost.iter().all(|row| row.len() == n) && rows <= n, "cost must have rows of equal length, at least as many columns as rows");
+    // rows past the real ones cost nothing anywhere, so they take the columns left over
+    let cost = |row: usize, column: usize| if row < rows { cost[row][column] as f64 } else { 0.0 };
+    // potentials for rows and columns, with a dummy column 0; rows and columns are 1-based
+    let mut u = vec![0.0f64; n + 1];
+    let mut v = vec![0.0f64; n + 1];
+    let mut row_of = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut column = 0;
+        let mut min_to = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[column] = true;
+            let current_row = row_of[column];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost(current_row - 1, j - 1) - u[current_row] - v[j];
+                if reduced < min_to[j] {
+                    min_to[j] = reduced;
+                    way[j] = column;
+                }
+                if min_to[j] < delta {
+                    delta = min_to[j];
+                    next = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+        // flip the augmenting path
+        loop {
+            let previous = way[column];
+            row_of[column] = row_of[previous];
+            column = previous;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=n {
+        if row_of[j] != 0 {
+            assignment[row_of[j] - 1] = j - 1;
+        }
+    }
+    assignment.truncate(rows);
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(cost: &[Vec<f32>], assignment: &[usize]) -> f32 {
+        assignment.iter().enumerate().map(|(row, &column)| cost[row][column]).sum()
+    }
+
+    #[test]
+    fn finds_the_cheapest_square_assignment() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        let assignment = solve(&cost);
+        assert_eq!(assignment, vec![1, 0, 2]);
+        assert_eq!(total(&cost, &assignment), 5.0);
+    }
+
+    #[test]
+    fn swaps_crossed_pairs() {
+        let cost = vec![vec![3.0, 0.5], vec![0.5, 3.0]];
+        assert_eq!(solve(&cost), vec![1, 0]);
+    }
+
+    #[test]
+    fn leaves_spare_columns_unused() {
+        let cost = vec![
+            vec![9.0, 2.0, 7.0, 8.0],
+            vec![6.0, 4.0, 3.0, 7.0],
+        ];
+        let assignment = solve(&cost);
+        assert_eq!(assignment, vec![1, 2]);
+        assert_eq!(total(&cost, &assignment), 5.0);
+    }
+
+    #[test]
+    fn solves_nothing_to_nothing() {
+        assert!(solve(&[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_more_rows_than_columns() {
+        solve(&[vec![1.0], vec![2.0]]);
+    }
+}
@@ -0,0 +1,376 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::{AudioConfig, AudioMapping, AudioSignal, AudioTarget};
+use crate::AUDIO;
+
+/// Samples per analysis block, per channel.
+const BLOCK_SIZE: usize = 1024;
+/// Crossovers between the low, mid and high bands, in Hz.
+const LOW_MID: f32 = 200.0;
+const MID_HIGH: f32 = 2000.0;
+/// Seconds the running peak each signal is normalized by takes to decay, so a quiet
+/// passage still moves the visuals.
+const PEAK_DECAY: f32 = 10.0;
+/// How much louder than the recent average a block must be to count as an onset.
+const ONSET_RATIO: f32 = 2.0;
+/// Seconds over which the recent average energy is taken.
+const ONSET_AVERAGE: f32 = 1.0;
+/// Minimum seconds between onsets.
+const ONSET_GAP: f32 = 0.1;
+/// Seconds the onset signal takes to fall to a third after a hit.
+const ONSET_DECAY: f32 = 0.15;
+
+/// The latest analysis, each signal normalized to roughly 0..=1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioSignals {
+    pub rms: f32,
+    /// 1 on a hit, decaying until the next.
+    pub onset: f32,
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl AudioSignals {
+    fn get(&self, signal: AudioSignal) -> f32 {
+        match signal {
+            AudioSignal::Rms => self.rms,
+            AudioSignal::Onset => self.onset,
+            AudioSignal::Low => self.low,
+            AudioSignal::Mid => self.mid,
+            AudioSignal::High => self.high,
+        }
+    }
+}
+
+/// A second order filter, from the RBJ audio EQ cookbook.
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn omega(frequency: f32, sample_rate: f32, q: f32) -> (f32, f32) {
+        let w = 2.0 * PI * frequency / sample_rate;
+        (w.cos(), w.sin() / (2.0 * q))
+    }
+
+    fn low_pass(frequency: f32, sample_rate: f32) -> Self {
+        let (cos, alpha) = Self::omega(frequency, sample_rate, std::f32::consts::FRAC_1_SQRT_2);
+        Self::new([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    fn high_pass(frequency: f32, sample_rate: f32) -> Self {
+        let (cos, alpha) = Self::omega(frequency, sample_rate, std::f32::consts::FRAC_1_SQRT_2);
+        Self::new([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Passes `low..high` with 0 dB at the geometric center.
+    fn band_pass(low: f32, high: f32, sample_rate: f32) -> Self {
+        let center = (low * high).sqrt();
+        let (cos, alpha) = Self::omega(center, sample_rate, center / (high - low));
+        Self::new([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1] - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Turns blocks of mono samples into `AudioSignals`.
+struct Analyzer {
+    block_time: f32,
+    bands: [Biquad; 3],
+    /// Running peaks of rms, low, mid and high.
+    peaks: [f32; 4],
+    average_energy: f32,
+    since_onset: f32,
+    onset: f32,
+}
+
+impl Analyzer {
+    /// Fails for sample rates too low to hold the high band, whose filters only make sense
+    /// below the Nyquist frequency.
+    fn new(sample_rate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let sample_rate = sample_rate as f32;
+        if sample_rate <= 2.0 * MID_HIGH {
+            return Err(format!("a sample rate of {} Hz is too low, it must be above {} Hz", sample_rate, 2.0 * MID_HIGH).into());
+        }
+        Ok(Self {
+            block_time: BLOCK_SIZE as f32 / sample_rate,
+            bands: [
+                Biquad::low_pass(LOW_MID, sample_rate),
+                Biquad::band_pass(LOW_MID, MID_HIGH, sample_rate),
+                Biquad::high_pass(MID_HIGH, sample_rate),
+            ],
+            peaks: [0.0; 4],
+            average_energy: 0.0,
+            since_onset: ONSET_GAP,
+            onset: 0.0,
+        })
+    }
+
+    fn analyze(&mut self, block: &[f32]) -> AudioSignals {
+        let mut energy = [0.0f32; 4];
+        for &sample in block {
+            energy[0] += sample * sample;
+            for (band, energy) in self.bands.iter_mut().zip(&mut energy[1..]) {
+                let filtered = band.process(sample);
+                *energy += filtered * filtered;
+            }
+        }
+        let energy = energy.map(|e| e / block.len().max(1) as f32);
+
+        let decay = (-self.block_time / PEAK_DECAY).exp();
+        let mut levels = [0.0f32; 4];
+        for ((level, peak), energy) in levels.iter_mut().zip(self.peaks.iter_mut()).zip(energy) {
+            let rms = energy.sqrt();
+            *peak = rms.max(*peak * decay);
+            *level = rms / peak.max(1e-4);
+        }
+
+        self.since_onset += self.block_time;
+        self.onset *= (-self.block_time / ONSET_DECAY).exp();
+        if energy[0] > self.average_energy * ONSET_RATIO && energy[0] > 1e-6 && self.since_onset >= ONSET_GAP {
+            self.onset = 1.0;
+            self.since_onset = 0.0;
+        }
+        let blend = 1.0 - (-self.block_time / ONSET_AVERAGE).exp();
+        self.average_energy += (energy[0] - self.average_energy) * blend;
+
+        AudioSignals {
+            rms: levels[0],
+            onset: self.onset,
+            low: levels[1],
+            mid: levels[2],
+            high: levels[3],
+        }
+    }
+}
+
+/// Starts analysing `audio.file` in real time, or `audio.stream` as it arrives, into
+/// `AUDIO`. Does nothing when neither is set.
+pub fn spawn_analysis(config: &AudioConfig) -> Result<(), Box<dyn std::error::Error>> {
+    match (&config.file, &config.stream) {
+        (Some(_), Some(_)) => Err("set either an audio file or an audio stream, not both".into()),
+        (Some(path), None) => {
+            // opened here so that a missing file stops the app before it starts
+            let reader = open_wav(path)?;
+            let spec = reader.spec();
+            let analyzer = Analyzer::new(spec.sample_rate).map_err(|e| format!("Cannot analyse audio file {}: {}", path.display(), e))?;
+            println!("Analysing {} ({} Hz, {} channels)", path.display(), spec.sample_rate, spec.channels);
+            let path = path.clone();
+            let looping = config.looping;
+            std::thread::spawn(move || analyse_file(reader, analyzer, &path, looping));
+            Ok(())
+        }
+        (None, Some(path)) => {
+            if config.channels == 0 || config.sample_rate == 0 {
+                return Err("an audio stream needs a sample rate and at least one channel".into());
+            }
+            if path != Path::new("-") && !path.exists() {
+                return Err(format!("Cannot open audio stream {}: no such file", path.display()).into());
+            }
+            let analyzer = Analyzer::new(config.sample_rate).map_err(|e| format!("Cannot analyse audio stream {}: {}", path.display(), e))?;
+            println!("Analysing audio from {} ({} Hz, {} channels)", path.display(), config.sample_rate, config.channels);
+            let path = path.clone();
+            let channels = config.channels as usize;
+            // a FIFO only opens once something writes to it, so this waits on its own thread
+            std::thread::spawn(move || {
+                let input: Box<dyn Read> = if path == Path::new("-") {
+                    Box::new(std::io::stdin())
+                } else {
+                    match File::open(&path) {
+                        Ok(file) => Box::new(file),
+                        Err(e) => {
+                            println!("Cannot open audio stream {}: {}", path.display(), e);
+                            return;
+                        }
+                    }
+                };
+                analyse_stream(input, analyzer, channels);
+            });
+            Ok(())
+        }
+        (None, None) => Ok(()),
+    }
+}
+
+type WavReader = hound::WavReader<BufReader<File>>;
+
+fn open_wav(path: &Path) -> Result<WavReader, Box<dyn std::error::Error>> {
+    Ok(hound::WavReader::open(path).map_err(|e| format!("Cannot open audio file {}: {}", path.display(), e))?)
+}
+
+/// Reads the next block of the file mixed down to mono, `None` at the end, or the error
+/// of a sample that would not decode.
+fn read_block(reader: &mut WavReader) -> Result<Option<Vec<f32>>, hound::Error> {
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().take(BLOCK_SIZE * channels).collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().take(BLOCK_SIZE * channels).map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()?
+        }
+    };
+    if samples.len() < channels {
+        return Ok(None);
+    }
+    Ok(Some(samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32).collect()))
+}
+
+/// Keeps the analysis in step with the clock, as if the file were playing.
+fn analyse_file(mut reader: WavReader, mut analyzer: Analyzer, path: &Path, looping: bool) {
+    let sample_rate = reader.spec().sample_rate;
+    let mut start = Instant::now();
+    let mut samples_done = 0u64;
+    loop {
+        let block = match read_block(&mut reader) {
+            Ok(Some(block)) => block,
+            Ok(None) if looping => {
+                reader = match open_wav(path) {
+                    Ok(reader) => reader,
+                    Err(e) => {
+                        println!("{}", e);
+                        break;
+                    }
+                };
+                start = Instant::now();
+                samples_done = 0;
+                continue;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                println!("Cannot decode audio file {}: {}", path.display(), e);
+                break;
+            }
+        };
+        samples_done += block.len() as u64;
+        let due = start + Duration::from_secs_f64(samples_done as f64 / sample_rate as f64);
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        *AUDIO.lock() = analyzer.analyze(&block);
+    }
+    *AUDIO.lock() = AudioSignals::default();
+}
+
+/// Interleaved little-endian f32 samples, paced by whatever is writing them.
+fn analyse_stream(mut input: Box<dyn Read>, mut analyzer: Analyzer, channels: usize) {
+    let mut bytes = vec![0u8; BLOCK_SIZE * channels * 4];
+    loop {
+        if let Err(e) = input.read_exact(&mut bytes) {
+            println!("Audio stream ended: {}", e);
+            break;
+        }
+        let block = bytes.chunks_exact(channels * 4)
+            .map(|frame| frame.chunks_exact(4).map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])).sum::<f32>() / channels as f32)
+            .collect::<Vec<_>>();
+        *AUDIO.lock() = analyzer.analyze(&block);
+    }
+    *AUDIO.lock() = AudioSignals::default();
+}
+
+/// How the audio currently scales each visual, 1.0 meaning unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioModulation {
+    pub dance_speed: f32,
+    pub seed_glow: f32,
+    pub ground_brightness: f32,
+    pub drift: f32,
+}
+
+/// Applies the configured mappings, each multiplying its target by 1 + amount × signal
+/// after smoothing the signal.
+pub struct AudioMapper {
+    mappings: Vec<AudioMapping>,
+    smoothed: Vec<f32>,
+}
+
+impl AudioMapper {
+    pub fn new(mappings: &[AudioMapping]) -> Self {
+        Self {
+            mappings: mappings.to_vec(),
+            smoothed: vec![0.0; mappings.len()],
+        }
+    }
+
+    pub fn update(&mut self, signals: &AudioSignals, dt: f32) -> AudioModulation {
+        let mut modulation = AudioModulation {
+            dance_speed: 1.0,
+            seed_glow: 1.0,
+            ground_brightness: 1.0,
+            drift: 1.0,
+        };
+        for (mapping, smoothed) in self.mappings.iter().zip(self.smoothed.iter_mut()) {
+            let value = signals.get(mapping.signal);
+            *smoothed = if mapping.smoothing > 0.0 {
+                *smoothed + (value - *smoothed) * (1.0 - (-dt / mapping.smoothing).exp())
+            } else {
+                value
+            };
+            let factor = (1.0 + mapping.amount * *smoothed).max(0.0);
+            match mapping.target {
+                AudioTarget::DanceSpeed => modulation.dance_speed *= factor,
+                AudioTarget::SeedGlow => modulation.seed_glow *= factor,
+                AudioTarget::GroundBrightness => modulation.ground_brightness *= factor,
+                AudioTarget::Drift => modulation.drift *= factor,
+            }
+        }
+        modulation
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyzer_needs_room_for_the_high_band() {
+        assert!(Analyzer::new(4000).is_err());
+        assert!(Analyzer::new(44100).is_ok());
+    }
+
+    #[test]
+    fn read_block_reports_a_truncated_file() {
+        let path = std::env::temp_dir().join(format!("dandelions-cut-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 1, sample_rate: 44100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..BLOCK_SIZE * 4 {
+            writer.write_sample((i % 100) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - BLOCK_SIZE * 3 - 1]).unwrap();
+
+        let mut reader = open_wav(&path).unwrap();
+        let mut blocks = 0;
+        let end = loop {
+            match read_block(&mut reader) {
+                Ok(Some(_)) => blocks += 1,
+                end => break end,
+            }
+        };
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(blocks, 2);
+        assert!(end.is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::{Config, SourceConfig, SourceKind};
+use crate::cues::ShowCommand;
+use crate::dancer_mock::MockSource;
+use crate::kinect_tracker::OscSource;
+use crate::puppet::{PuppetHandle, PuppetSource};
+use crate::recording::{Recorder, ReplayHandle, ReplaySource};
+use crate::skeleton::JointUpdate;
+use crate::SKELETON;
+
+/// Something that produces bodies for the skeleton, such as the Kinect over OSC or the
+/// dancer mock.
+pub trait BodySource {
+    /// Starts producing frames on a thread of its own, pushing them into `sink`.
+    fn start(self: Box<Self>, sink: BodySink) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Where a source delivers its frames. It only lets through the bodies the source has
+/// been assigned, so that sources never fight over the same body.
+#[derive(Clone)]
+pub struct BodySink {
+    bodies: Option<Vec<usize>>,
+    /// Bodies assigned explicitly to some source, which a catch-all source leaves alone.
+    claimed: Arc<Vec<usize>>,
+}
+
+impl BodySink {
+    /// The bodies assigned to the source, or `None` if it takes every unclaimed body.
+    pub fn bodies(&self) -> Option<&[usize]> {
+        self.bodies.as_deref()
+    }
+
+    pub fn accepts(&self, body: usize) -> bool {
+        match &self.bodies {
+            Some(bodies) => bodies.contains(&body),
+            None => !self.claimed.contains(&body),
+        }
+    }
+
+    pub fn apply(&self, updates: &[JointUpdate], time: Instant) {
+        let updates = updates.iter().filter(|update| self.accepts(update.body)).copied().collect::<Vec<_>>();
+        if !updates.is_empty() {
+            SKELETON.lock().apply(&updates, time);
+        }
+    }
+}
+
+/// Controls for the sources that are driven from the app.
+#[derive(Default)]
+pub struct SourceHandles {
+    pub replay: Option<ReplayHandle>,
+    pub puppet: Option<PuppetHandle>,
+    /// Show commands received over OSC, when `osc.control` is on.
+    pub show: Option<Receiver<ShowCommand>>,
+}
+
+/// A sink for each configured source, in order. Each body belongs to the one source that
+/// lists it, and at most one source may leave out `bodies` to take all the others.
+pub fn sinks(config: &Config) -> Result<Vec<BodySink>, Box<dyn std::error::Error>> {
+    let mut claimed = Vec::new();
+    let mut catch_all = None;
+    for source in &config.sources {
+        match &source.bodies {
+            Some(bodies) => {
+                for body in bodies {
+                    if claimed.contains(body) {
+                        return Err(format!("body {} is assigned to more than one source", body).into());
+                    }
+                    claimed.push(*body);
+                }
+            }
+            None => {
+                if let Some(other) = catch_all.replace(source.kind) {
+                    return Err(format!("the {} and {} sources both take every body; give one of them bodies", other.name(), source.kind.name()).into());
+                }
+            }
+        }
+    }
+    let claimed = Arc::new(claimed);
+    Ok(config.sources.iter()
+        .map(|source| BodySink {
+            bodies: source.bodies.clone(),
+            claimed: claimed.clone(),
+        })
+        .collect())
+}
+
+/// Starts every configured source, see `sinks`.
+pub fn start_sources(config: &Config) -> Result<SourceHandles, Box<dyn std::error::Error>> {
+    let sinks = sinks(config)?;
+
+    let mut handles = SourceHandles::default();
+    let mut commands = None;
+    if config.osc.control {
+        let (sender, receiver) = mpsc::channel();
+        commands = Some(sender);
+        handles.show = Some(receiver);
+    }
+    for (SourceConfig { kind, bodies }, sink) in config.sources.iter().zip(sinks) {
+        let source: Box<dyn BodySource> = match kind {
+            SourceKind::Osc => {
+                let recorder = config.osc.record.as_deref().map(Recorder::create).transpose()?;
+                Box::new(OscSource::new(config.osc.clone(), recorder, commands.take())?)
+            }
+            SourceKind::Mock => Box::new(MockSource::new(&config.mock, config.random.seed)?),
+            SourceKind::Replay => {
+                let (source, handle) = ReplaySource::new(&config.replay)?;
+                handles.replay = Some(handle);
+                Box::new(source)
+            }
+            SourceKind::Puppet => {
+                let puppet_bodies = bodies.clone().filter(|bodies| !bodies.is_empty())
+                    .ok_or("the puppet source needs its bodies listed")?;
+                let (source, handle) = PuppetSource::new(puppet_bodies);
+                handles.puppet = Some(handle);
+                Box::new(source)
+            }
+        };
+        source.start(sink)?;
+    }
+    if let Some(commands) = commands {
+        // nothing is tracked over OSC, but the show can still be controlled with it
+        let sink = BodySink {
+            bodies: Some(Vec::new()),
+            claimed: Arc::new(Vec::new()),
+        };
+        Box::new(OscSource::new(config.osc.clone(), None, Some(commands))?).start(sink)?;
+    }
+    Ok(handles)
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use crate::affine_matrix::AffineMatrix;
+use crate::config::CalibrationConfig;
+use crate::skeleton::{Joint, Skeleton};
+use crate::vector::{cross, distance, length, sub};
+
+/// The transform used before any calibration: the sensor faces the audience, so X and Z
+/// are mirrored, which is a half turn about Y.
+pub fn default_transform() -> AffineMatrix {
+    let mut transform = AffineMatrix::new();
+    transform.set_scale(-1.0, 1.0, -1.0);
+    transform
+}
+
+pub fn load(path: &Path) -> Result<AffineMatrix, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read calibration {}: {}", path.display(), e))?;
+    let transform = toml::from_str(&text)
+        .map_err(|e| format!("Cannot parse calibration {}: {}", path.display(), e))?;
+    Ok(transform)
+}
+
+pub fn save(path: &Path, transform: &AffineMatrix) -> Result<(), Box<dyn std::error::Error>> {
+    let text = toml::to_string(transform)?;
+    std::fs::write(path, text)
+        .map_err(|e| format!("Cannot write calibration {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Walks a dancer through the stage marks from the config, capturing where the tracker
+/// currently places them, then solves for the correction.
+pub struct CalibrationSession {
+    body: usize,
+    joint: Joint,
+    targets: Vec<[f32; 3]>,
+    captured: Vec<[f32; 3]>,
+}
+
+impl CalibrationSession {
+    pub fn new(config: &CalibrationConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if !spans_plane(&config.points) {
+            return Err("calibration needs at least 3 stage points that are not in a line".into());
+        }
+        let joint = Joint::from_name(&config.joint)
+            .ok_or_else(|| format!("unknown calibration joint {}", config.joint))?;
+        Ok(Self {
+            body: config.body,
+            joint,
+            targets: config.points.clone(),
+            captured: Vec::new(),
+        })
+    }
+
+    /// The stage point the dancer should be standing on next, with its number.
+    pub fn current_target(&self) -> Option<(usize, [f32; 3])> {
+        let index = self.captured.len();
+        self.targets.get(index).map(|target| (index + 1, *target))
+    }
+
+    pub fn num_targets(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.captured.len() == self.targets.len()
+    }
+
+    pub fn capture(&mut self, skeleton: &Skeleton) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_complete() {
+            return Err("all calibration points are already captured".into());
+        }
+        let sample = skeleton.joint(self.body, self.joint)
+            .ok_or_else(|| format!("body {} {} is not being tracked", self.body, self.joint.name()))?;
+        self.captured.push(sample.position);
+        Ok(())
+    }
+
+    pub fn undo(&mut self) {
+        self.captured.pop();
+    }
+
+    /// Returns the calibration replacing `current`, and the RMS distance in meters between
+    /// the corrected captures and the stage points.
+    pub fn solve(&self, current: &AffineMatrix) -> Result<(AffineMatrix, f32), Box<dyn std::error::Error>> {
+        let correction = solve_rigid(&self.captured, &self.targets)?;
+        let squared_error = self.captured.iter().zip(&self.targets)
+            .map(|(captured, target)| {
+                distance(correction.transform_point(*captured), *target).powi(2)
+            })
+            .sum::<f32>();
+        let rms = (squared_error / self.targets.len() as f32).sqrt();
+        Ok((*current * correction, rms))
+    }
+}
+
+fn spans_plane(points: &[[f32; 3]]) -> bool {
+    let (first, second) = match points {
+        [first, second, ..] => (*first, sub(*second, *first)),
+        _ => return false,
+    };
+    points[2..].iter().any(|p| length(cross(second, sub(*p, first))) > 1e-3)
+}
+
+/// Finds the rotation and translation that best maps `from` onto `to` in the least squares
+/// sense, using Horn's closed-form quaternion solution.
+fn solve_rigid(from: &[[f32; 3]], to: &[[f32; 3]]) -> Result<AffineMatrix, Box<dyn std::error::Error>> {
+    assert_eq!(from.len(), to.len());
+    let n = from.len() as f64;
+    let centroid = |points: &[[f32; 3]]| {
+        let mut c = [0.0f64; 3];
+        for p in points {
+            for i in 0..3 {
+                c[i] += p[i] as f64 / n;
+            }
+        }
+        c
+    };
+    let from_centroid = centroid(from);
+    let to_centroid = centroid(to);
+
+    // cross covariance of the centered point sets
+    let mut s = [[0.0f64; 3]; 3];
+    for (p, q) in from.iter().zip(to) {
+        for a in 0..3 {
+            for b in 0..3 {
+                s[a][b] += (p[a] as f64 - from_centroid[a]) * (q[b] as f64 - to_centroid[b]);
+            }
+        }
+    }
+    let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = s;
+    let k = [
+        [sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+        [syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+        [szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+        [sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+    ];
+
+    // the optimal rotation is the eigenvector of the largest eigenvalue; shifting by the
+    // norm makes every eigenvalue non-negative so power iteration finds it
+    let norm = k.iter().flatten().map(|v| v * v).sum::<f64>().sqrt();
+    if norm < 1e-9 {
+        return Err("calibration points are all in the same place".into());
+    }
+    let mut quat = [1.0f64, 0.1, 0.1, 0.1];
+    for _ in 0..1000 {
+        let mut next = [0.0f64; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                next[i] += (k[i][j] + if i == j { norm } else { 0.0 }) * quat[j];
+            }
+        }
+        let length = next.iter().map(|v| v * v).sum::<f64>().sqrt();
+        quat = next.map(|v| v / length);
+    }
+
+    let [w, x, y, z] = quat;
+    let rotation = [
+        [w * w + x * x - y * y - z * z, 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), w * w - x * x + y * y - z * z, 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), w * w - x * x - y * y + z * z],
+    ];
+
+    let mut transform = AffineMatrix::new();
+    for (i, row) in rotation.iter().enumerate() {
+        let rotated_centroid = row.iter().zip(&from_centroid).map(|(r, c)| r * c).sum::<f64>();
+        transform.matrix[3][i] = (to_centroid[i] - rotated_centroid) as f32;
+        for (j, r) in row.iter().enumerate() {
+            // the shaders' convention keeps points as rows, so the rotation is transposed
+            transform.matrix[j][i] = *r as f32;
+        }
+    }
+    Ok(transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stage marks turned by `angle` about Y, then moved by `offset`.
+    fn moved(points: &[[f32; 3]], angle: f32, offset: [f32; 3]) -> Vec<[f32; 3]> {
+        let (sin, cos) = angle.sin_cos();
+        points.iter()
+            .map(|&[x, y, z]| [cos * x + sin * z + offset[0], y + offset[1], -sin * x + cos * z + offset[2]])
+            .collect()
+    }
+
+    fn assert_close(left: [f32; 3], right: [f32; 3]) {
+        assert!((0..3).all(|i| (left[i] - right[i]).abs() < 1e-4), "{:?} is not {:?}", left, right);
+    }
+
+    #[test]
+    fn recovers_a_rigid_move() {
+        let from = [[-1.0, 0.0, -1.5], [1.0, 0.0, -1.5], [1.0, 0.2, -3.0], [-1.0, 0.0, -3.0]];
+        let to = moved(&from, 0.5, [0.3, -0.1, 0.8]);
+        let transform = solve_rigid(&from, &to).unwrap();
+        for (point, target) in from.iter().zip(&to) {
+            assert_close(transform.transform_point(*point), *target);
+        }
+    }
+
+    #[test]
+    fn recovers_the_identity() {
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let transform = solve_rigid(&points, &points).unwrap();
+        let probe = [0.4, 1.2, -2.0];
+        assert_close(transform.transform_point(probe), probe);
+    }
+
+    #[test]
+    fn rejects_points_in_one_place() {
+        let points = [[1.0, 2.0, 3.0]; 3];
+        assert!(solve_rigid(&points, &points).is_err());
+    }
+
+    #[test]
+    fn needs_points_off_a_line() {
+        assert!(!spans_plane(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]]));
+        assert!(!spans_plane(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]]));
+        assert!(spans_plane(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]));
+    }
+}
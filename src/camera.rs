@@ -0,0 +1,99 @@
+use crate::affine_matrix::AffineMatrix;
+use crate::config::CameraConfig;
+
+/// The virtual camera the scene is drawn from, set up to match where the projector hangs
+/// and how it throws: it looks down -Z before turning, and lens shift moves the image
+/// without tilting the camera, as a projector's lens shift does.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub position: [f32; 3],
+    /// Radians turned to the right.
+    pub yaw: f32,
+    /// Radians tilted up.
+    pub pitch: f32,
+    /// Radians turned clockwise about the view direction.
+    pub roll: f32,
+    /// Vertical field of view, in radians.
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Shift of the image as a fraction of its width and height, +X right and +Y up.
+    pub lens_shift: [f32; 2],
+}
+
+impl Camera {
+    pub fn new(config: &CameraConfig) -> Self {
+        Self {
+            position: config.position,
+            yaw: config.yaw.to_radians(),
+            pitch: config.pitch.to_radians(),
+            roll: config.roll.to_radians(),
+            fov: config.fov.to_radians(),
+            near: config.near,
+            far: config.far,
+            lens_shift: config.lens_shift,
+        }
+    }
+
+    /// Turns camera space into world space, leaving out the position.
+    fn orientation(&self) -> AffineMatrix {
+        let mut roll = AffineMatrix::new();
+        roll.set_rotate_z(self.roll);
+        let mut pitch = AffineMatrix::new();
+        pitch.set_rotate_x(-self.pitch);
+        let mut yaw = AffineMatrix::new();
+        yaw.set_rotate_y(self.yaw);
+        roll * pitch * yaw
+    }
+
+    /// Turns world space into camera space.
+    pub fn view_matrix(&self) -> AffineMatrix {
+        let mut translation = AffineMatrix::new();
+        translation.set_translate(-self.position[0], -self.position[1], -self.position[2]);
+        let mut yaw = AffineMatrix::new();
+        yaw.set_rotate_y(-self.yaw);
+        let mut pitch = AffineMatrix::new();
+        pitch.set_rotate_x(self.pitch);
+        let mut roll = AffineMatrix::new();
+        roll.set_rotate_z(-self.roll);
+        translation * yaw * pitch * roll
+    }
+
+    /// A perspective frustum for part of an image `aspect` times wider than it is tall, moved
+    /// off axis by the lens shift. `slice` is the part drawn, as [left, bottom, right, top]
+    /// fractions of the whole image; [0, 0, 1, 1] draws all of it.
+    pub fn projection_matrix(&self, aspect: f32, slice: [f32; 4]) -> AffineMatrix {
+        let (n, f) = (self.near, self.far);
+        let top = n * (self.fov / 2.0).tan();
+        let right = top * aspect;
+        // the shift is a fraction of the whole image, which spans twice the half width
+        let shift_x = 2.0 * self.lens_shift[0] * right;
+        let shift_y = 2.0 * self.lens_shift[1] * top;
+        let across = |fraction: f32, half: f32, shift: f32| -half + shift + 2.0 * half * fraction;
+        let (l, r) = (across(slice[0], right, shift_x), across(slice[2], right, shift_x));
+        let (b, t) = (across(slice[1], top, shift_y), across(slice[3], top, shift_y));
+        AffineMatrix {
+            matrix: [
+                [2.0 * n / (r - l), 0.0, 0.0, 0.0],
+                [0.0, 2.0 * n / (t - b), 0.0, 0.0],
+                [(r + l) / (r - l), (t + b) / (t - b), -(f + n) / (f - n), -1.0],
+                [0.0, 0.0, -2.0 * f * n / (f - n), 0.0],
+            ],
+        }
+    }
+
+    /// Moves the camera `forward` meters the way it faces and `right` meters to its right,
+    /// keeping its height.
+    pub fn move_by(&mut self, forward: f32, right: f32) {
+        let orientation = self.orientation();
+        let ahead = orientation.transform_point([0.0, 0.0, -1.0]);
+        let side = orientation.transform_point([1.0, 0.0, 0.0]);
+        let level = |v: [f32; 3]| {
+            let length = (v[0] * v[0] + v[2] * v[2]).sqrt();
+            if length > 1e-6 { [v[0] / length, v[2] / length] } else { [0.0, 0.0] }
+        };
+        let (ahead, side) = (level(ahead), level(side));
+        self.position[0] += forward * ahead[0] + right * side[0];
+        self.position[2] += forward * ahead[1] + right * side[1];
+    }
+}
@@ -0,0 +1,316 @@
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::path::Path;
+
+use crate::skeleton::{Joint, JointUpdate};
+
+/// Meters per second at which the head bobs fully.
+const WALKING_SPEED: f32 = 1.0;
+
+/// A scripted rehearsal for the dancer mock: every body starts somewhere on stage and
+/// runs through its moves in order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Script {
+    /// Starts every body over from `start` once it has run out of moves.
+    #[serde(default = "default_loop", rename = "loop")]
+    looping: bool,
+    bodies: Vec<ScriptedBody>,
+}
+
+fn default_loop() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptedBody {
+    body: usize,
+    start: [f32; 3],
+    #[serde(default)]
+    head: HeadMotion,
+    #[serde(default)]
+    moves: Vec<Move>,
+}
+
+/// How the head follows the spine.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HeadMotion {
+    /// Height of the head above SpineMid.
+    height: f32,
+    /// Meters the head leans into the walk per meter per second of speed.
+    lean: f32,
+    max_lean: f32,
+    /// How far the head rises and falls with each step.
+    bob: f32,
+    step_length: f32,
+}
+
+impl Default for HeadMotion {
+    fn default() -> Self {
+        Self {
+            height: 0.2,
+            lean: 0.05,
+            max_lean: 0.1,
+            bob: 0.02,
+            step_length: 0.7,
+        }
+    }
+}
+
+/// Every move starts from where the previous one ended, positions are in stage
+/// coordinates and durations in seconds.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum Move {
+    Hold {
+        duration: f32,
+    },
+    /// Walks straight to `to`.
+    Line {
+        to: [f32; 3],
+        duration: f32,
+    },
+    /// Walks around `center` at the current distance from it, counterclockwise seen from
+    /// above for positive `turns`.
+    Circle {
+        center: [f32; 3],
+        turns: f32,
+        duration: f32,
+    },
+    /// Walks towards or away from `partner` until `distance` meters apart, following it
+    /// as it moves.
+    #[serde(alias = "retreat")]
+    Approach {
+        partner: usize,
+        distance: f32,
+        duration: f32,
+    },
+    /// Walks to where `partner` stood when the move began, stepping `offset` meters to
+    /// the side on the way to pass it.
+    Cross {
+        partner: usize,
+        #[serde(default = "default_cross_offset")]
+        offset: f32,
+        duration: f32,
+    },
+}
+
+fn default_cross_offset() -> f32 {
+    0.5
+}
+
+impl Move {
+    fn duration(&self) -> f32 {
+        match *self {
+            Move::Hold { duration }
+            | Move::Line { duration, .. }
+            | Move::Circle { duration, .. }
+            | Move::Approach { duration, .. }
+            | Move::Cross { duration, .. } => duration,
+        }
+    }
+
+    fn partner(&self) -> Option<usize> {
+        match *self {
+            Move::Approach { partner, .. } | Move::Cross { partner, .. } => Some(partner),
+            _ => None,
+        }
+    }
+}
+
+struct Dancer {
+    body: usize,
+    start: [f32; 3],
+    head: HeadMotion,
+    moves: Vec<Move>,
+    index: usize,
+    elapsed: f32,
+    /// Where the current move began, and where its partner was at that moment.
+    from: [f32; 3],
+    partner_from: [f32; 3],
+    position: [f32; 3],
+    velocity: [f32; 3],
+    bob_phase: f32,
+}
+
+/// Plays a script loaded from a file, one step per frame of the mock.
+pub struct Choreography {
+    dancers: Vec<Dancer>,
+    looping: bool,
+}
+
+impl Choreography {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read mock script {}: {}", path.display(), e))?;
+        let script: Script = toml::from_str(&text)
+            .map_err(|e| format!("Cannot parse mock script {}: {}", path.display(), e))?;
+        Self::new(script).map_err(|e| format!("Invalid mock script {}: {}", path.display(), e).into())
+    }
+
+    fn new(script: Script) -> Result<Self, String> {
+        let bodies = script.bodies.iter().map(|body| body.body).collect::<Vec<_>>();
+        for (i, scripted) in script.bodies.iter().enumerate() {
+            if bodies[..i].contains(&scripted.body) {
+                return Err(format!("body {} is scripted twice", scripted.body));
+            }
+            for step in &scripted.moves {
+                if step.duration() <= 0.0 {
+                    return Err(format!("body {} has a move that does not take any time", scripted.body));
+                }
+                if let Some(partner) = step.partner() {
+                    if partner == scripted.body || !bodies.contains(&partner) {
+                        return Err(format!("body {} moves relative to body {}, which is not another scripted body", scripted.body, partner));
+                    }
+                }
+            }
+        }
+
+        let dancers = script.bodies.into_iter()
+            .map(|scripted| Dancer {
+                body: scripted.body,
+                start: scripted.start,
+                head: scripted.head,
+                moves: scripted.moves,
+                index: 0,
+                elapsed: 0.0,
+                from: scripted.start,
+                partner_from: scripted.start,
+                position: scripted.start,
+                velocity: [0.0; 3],
+                bob_phase: 0.0,
+            })
+            .collect::<Vec<_>>();
+        let mut choreography = Self {
+            dancers,
+            looping: script.looping,
+        };
+        let positions = choreography.positions();
+        for dancer in choreography.dancers.iter_mut() {
+            dancer.begin_move(&positions);
+        }
+        Ok(choreography)
+    }
+
+    pub fn num_bodies(&self) -> usize {
+        self.dancers.len()
+    }
+
+    fn positions(&self) -> Vec<(usize, [f32; 3])> {
+        self.dancers.iter().map(|dancer| (dancer.body, dancer.position)).collect()
+    }
+
+    /// Advances the script by `dt` seconds and returns the spine and head of every body.
+    pub fn step(&mut self, dt: f32) -> Vec<JointUpdate> {
+        let positions = self.positions();
+        let mut updates = Vec::with_capacity(self.dancers.len() * 2);
+        for dancer in self.dancers.iter_mut() {
+            dancer.step(dt, &positions, self.looping);
+            updates.push(JointUpdate { body: dancer.body, joint: Joint::SpineMid, position: dancer.position });
+            updates.push(JointUpdate { body: dancer.body, joint: Joint::Head, position: dancer.head_position() });
+        }
+        updates
+    }
+}
+
+fn partner_position(positions: &[(usize, [f32; 3])], partner: usize) -> [f32; 3] {
+    positions.iter().find(|(body, _)| *body == partner).map(|(_, position)| *position).unwrap_or_default()
+}
+
+fn lerp(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t)
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Dancer {
+    fn begin_move(&mut self, positions: &[(usize, [f32; 3])]) {
+        self.from = self.position;
+        if let Some(partner) = self.moves.get(self.index).and_then(Move::partner) {
+            self.partner_from = partner_position(positions, partner);
+        }
+    }
+
+    fn step(&mut self, dt: f32, positions: &[(usize, [f32; 3])], looping: bool) {
+        if self.index >= self.moves.len() {
+            if !looping || self.moves.is_empty() {
+                self.velocity = [0.0; 3];
+                return;
+            }
+            self.index = 0;
+            self.position = self.start;
+            self.begin_move(positions);
+        }
+
+        let step = self.moves[self.index];
+        self.elapsed += dt;
+        let t = (self.elapsed / step.duration()).min(1.0);
+        let previous = self.position;
+        self.position = self.evaluate(step, t, positions);
+        if dt > 0.0 {
+            self.velocity = [0, 1, 2].map(|i| (self.position[i] - previous[i]) / dt);
+        }
+        let speed = self.velocity[0].hypot(self.velocity[2]);
+        self.bob_phase = (self.bob_phase + 2.0 * PI * speed * dt / self.head.step_length) % (2.0 * PI);
+
+        if t >= 1.0 {
+            self.index += 1;
+            self.elapsed = 0.0;
+            self.begin_move(positions);
+        }
+    }
+
+    fn evaluate(&self, step: Move, t: f32, positions: &[(usize, [f32; 3])]) -> [f32; 3] {
+        let from = self.from;
+        match step {
+            Move::Hold { .. } => from,
+            Move::Line { to, .. } => lerp(from, to, smoothstep(t)),
+            Move::Circle { center, turns, .. } => {
+                let (x, z) = (from[0] - center[0], from[2] - center[2]);
+                let radius = x.hypot(z);
+                // counterclockwise from above, with z towards the audience
+                let angle = (-z).atan2(x) + turns * 2.0 * PI * t;
+                [center[0] + radius * angle.cos(), from[1], center[2] - radius * angle.sin()]
+            }
+            Move::Approach { partner, distance, .. } => {
+                let partner = partner_position(positions, partner);
+                let (x, z) = (from[0] - partner[0], from[2] - partner[2]);
+                let length = x.hypot(z);
+                let direction = if length > 1e-4 { [x / length, z / length] } else { [1.0, 0.0] };
+                let target = [partner[0] + direction[0] * distance, from[1], partner[2] + direction[1] * distance];
+                lerp(from, target, smoothstep(t))
+            }
+            Move::Cross { offset, .. } => {
+                let target = [self.partner_from[0], from[1], self.partner_from[2]];
+                let mut position = lerp(from, target, smoothstep(t));
+                let (x, z) = (target[0] - from[0], target[2] - from[2]);
+                let length = x.hypot(z);
+                if length > 1e-4 {
+                    // to the walker's right, as far as `offset` half way across
+                    let side = offset * (PI * smoothstep(t)).sin();
+                    position[0] += -z / length * side;
+                    position[2] += x / length * side;
+                }
+                position
+            }
+        }
+    }
+
+    fn head_position(&self) -> [f32; 3] {
+        let head = self.head;
+        let mut lean = [self.velocity[0] * head.lean, self.velocity[2] * head.lean];
+        let length = lean[0].hypot(lean[1]);
+        if length > head.max_lean {
+            lean = lean.map(|v| v * head.max_lean / length);
+        }
+        // the bob fades out as the dancer slows to a stop
+        let bob = head.bob * (self.velocity[0].hypot(self.velocity[2]) / WALKING_SPEED).min(1.0) * self.bob_phase.sin();
+        let [x, y, z] = self.position;
+        [x + lean[0], y + head.height + bob, z + lean[1]]
+    }
+}
+
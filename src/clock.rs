@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+/// The show's time. Live it follows the wall clock; rendering, it only moves on when told
+/// to, one fixed step at a time, so a run comes out the same however long each frame
+/// takes to draw.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    start: Instant,
+    /// How far each tick moves a fixed clock on, `None` for the wall clock.
+    step: Option<Duration>,
+    ticks: u32,
+}
+
+impl Clock {
+    pub fn realtime() -> Self {
+        Self {
+            start: Instant::now(),
+            step: None,
+            ticks: 0,
+        }
+    }
+
+    /// A clock standing at its start until `tick` moves it on by `step`.
+    pub fn fixed(step: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            step: Some(step),
+            ticks: 0,
+        }
+    }
+
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    pub fn now(&self) -> Instant {
+        match self.step {
+            Some(step) => self.start + step * self.ticks,
+            None => Instant::now(),
+        }
+    }
+
+    /// Seconds since the start.
+    pub fn elapsed(&self) -> f64 {
+        self.now().saturating_duration_since(self.start).as_secs_f64()
+    }
+
+    /// Moves a fixed clock on by one step; the wall clock moves by itself.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+}
@@ -0,0 +1,929 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG_PATH: &str = "./dandelions.toml";
+
+const USAGE: &str = "usage: dandelions [--config <file>] [--osc-address <ipv4>] [--osc-port <port>] [--calibration <file>]
+                  [--record <file>] [--replay <file>] [--replay-speed <x>] [--replay-mode realtime|step]
+                  [--sources <kind[:body+body...]>,...]   e.g. --sources osc:1,mock:2
+                  [--mock-script <file>] [--mock-speed <x>] [--cues <file>]
+                  [--osc-out <ip:port>] [--osc-out-rate <hz>] [--audio <wav>] [--audio-stream <path or ->]
+                  [--warp <file>] [--render <dir>] [--render-size <width>x<height>] [--render-fps <fps>]
+                  [--render-duration <seconds>] [--seed <n>]";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub osc: OscConfig,
+    pub calibration: CalibrationConfig,
+    pub filters: FiltersConfig,
+    pub tracking: TrackingConfig,
+    pub replay: ReplayConfig,
+    pub sources: Vec<SourceConfig>,
+    pub mock: MockConfig,
+    pub cues: CuesConfig,
+    pub osc_out: OscOutputConfig,
+    pub audio: AudioConfig,
+    pub seeds: Vec<SeedBehavior>,
+    pub seed_head: SeedHeadConfig,
+    pub wind: WindConfig,
+    pub flight: FlightConfig,
+    pub camera: CameraConfig,
+    pub window: WindowConfig,
+    pub outputs: Vec<OutputConfig>,
+    pub warp: WarpConfig,
+    pub post: PostConfig,
+    pub render: RenderConfig,
+    pub random: RandomConfig,
+}
+
+/// Where the bodies come from, see `body_source::start_sources`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    pub kind: SourceKind,
+    /// The bodies this source drives. Left out, it drives every body no other source lists.
+    pub bodies: Option<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// The tracker, as set up in `[osc]`.
+    Osc,
+    /// Dancers wandering at random.
+    Mock,
+    /// The recording set up in `[replay]`.
+    Replay,
+    /// Bodies moved with the mouse and keyboard.
+    Puppet,
+}
+
+/// What one seed does, see `Scene::update`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "behavior", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SeedBehavior {
+    /// Hovers over a dancer's head, leaning towards the nearest other dancer with affection.
+    Follow { body: usize },
+    /// Follows like `follow`, and floats off as the drift level rises.
+    Drift { body: usize },
+    /// Circles with the other dancing seeds while the dancing level is up.
+    Dance,
+    /// Floats gently about one spot.
+    Idle { position: [f32; 3] },
+}
+
+impl SeedBehavior {
+    /// The body a following seed is with.
+    pub fn body(&self) -> Option<usize> {
+        match *self {
+            SeedBehavior::Follow { body } | SeedBehavior::Drift { body } => Some(body),
+            SeedBehavior::Dance | SeedBehavior::Idle { .. } => None,
+        }
+    }
+
+    pub fn set_body(&mut self, to: usize) {
+        if let SeedBehavior::Follow { body } | SeedBehavior::Drift { body } = self {
+            *body = to;
+        }
+    }
+}
+
+impl SourceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceKind::Osc => "osc",
+            SourceKind::Mock => "mock",
+            SourceKind::Replay => "replay",
+            SourceKind::Puppet => "puppet",
+        }
+    }
+}
+
+impl std::str::FromStr for SourceConfig {
+    type Err = Box<dyn std::error::Error>;
+
+    /// Parses `kind` or `kind:body+body`, e.g. `mock:2+3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, bodies) = match s.split_once(':') {
+            Some((kind, bodies)) => (kind, Some(bodies.split('+').map(str::parse).collect::<Result<Vec<_>, _>>()?)),
+            None => (s, None),
+        };
+        let kind = match kind {
+            "osc" => SourceKind::Osc,
+            "mock" => SourceKind::Mock,
+            "replay" => SourceKind::Replay,
+            "puppet" => SourceKind::Puppet,
+            _ => return Err(format!("unknown source {}, expected osc, mock, replay or puppet", kind).into()),
+        };
+        Ok(Self { kind, bodies })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OscConfig {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    /// Tried in order against every incoming address, see `kinect_tracker::JointPattern`.
+    pub joints: Vec<JointMapping>,
+    /// Records every received joint to this file, see `recording::Recorder`.
+    pub record: Option<PathBuf>,
+    /// Accepts show control messages under `/show/`, see `kinect_tracker::handle_control_msg`.
+    /// Off by default; when on, the listener binds the port even when no source is `osc`.
+    pub control: bool,
+}
+
+/// Where the scene is published over OSC, see `osc_output::OscOutput`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OscOutputConfig {
+    pub destination: Option<SocketAddr>,
+    /// Reports per second, 0 sends one every frame.
+    pub rate: f32,
+}
+
+impl Default for OscOutputConfig {
+    fn default() -> Self {
+        Self {
+            destination: None,
+            rate: 30.0,
+        }
+    }
+}
+
+/// What the visuals listen to, see `audio::spawn_analysis`: a WAV file analysed in real
+/// time, or a stream of raw interleaved little-endian f32 samples such as a FIFO or `-` for
+/// standard input.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub file: Option<PathBuf>,
+    /// Starts the file over when it ends.
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    pub stream: Option<PathBuf>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub mappings: Vec<AudioMapping>,
+}
+
+/// Scales `target` by 1 + `amount` × `signal`, with the signal smoothed over `smoothing`
+/// seconds. A negative amount turns the target down as the signal rises.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioMapping {
+    pub signal: AudioSignal,
+    pub target: AudioTarget,
+    pub amount: f32,
+    #[serde(default = "default_audio_smoothing")]
+    pub smoothing: f32,
+}
+
+fn default_audio_smoothing() -> f32 {
+    0.1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioSignal {
+    Rms,
+    Onset,
+    Low,
+    Mid,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioTarget {
+    DanceSpeed,
+    SeedGlow,
+    GroundBrightness,
+    Drift,
+}
+
+/// The dandelion clock that scatters its seeds, see `seed_head::SeedHead`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeedHeadConfig {
+    pub enabled: bool,
+    /// Middle of the head.
+    pub position: [f32; 3],
+    /// Meters from the middle of the head to where the seeds are attached.
+    pub radius: f32,
+    pub seeds: usize,
+    /// Scale of each seed's mesh.
+    pub seed_size: f32,
+    /// How close, in meters, a dancer's joint must come to a seed to knock it off.
+    pub release_distance: f32,
+    /// Seconds over which a released head lets go of all its seeds.
+    pub release_spread: f32,
+    /// How quickly a seed comes to move with the air, per second.
+    pub drag: f32,
+    /// Speed a seed falls at in still air, in meters per second.
+    pub sink_speed: f32,
+    /// Upward acceleration per meter per second of wind across a seed.
+    pub lift: f32,
+    /// How much a flying seed tips and turns, in turns per second.
+    pub tumble: f32,
+}
+
+impl Default for SeedHeadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: [0.8, -0.3, -2.5],
+            radius: 0.02,
+            seeds: 120,
+            seed_size: 0.012,
+            release_distance: 0.15,
+            release_spread: 2.0,
+            drag: 1.5,
+            sink_speed: 0.3,
+            lift: 0.3,
+            tumble: 0.5,
+        }
+    }
+}
+
+/// The air the seeds blow about in, see `wind::WindField`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindConfig {
+    /// The wind with nobody moving, in meters per second.
+    pub steady: [f32; 3],
+    /// Speed of the swirls in the wind, in meters per second.
+    pub turbulence: f32,
+    /// Size of the swirls, in meters.
+    pub turbulence_scale: f32,
+    /// How quickly the swirls change, in swirl sizes per second.
+    pub turbulence_speed: f32,
+    /// Fraction of a joint's speed it gives the air around it.
+    pub gust_strength: f32,
+    /// Meters around a joint that it stirs.
+    pub gust_radius: f32,
+    /// Seconds a gust takes to die down to about a third.
+    pub gust_decay: f32,
+    /// Meters between the points the gusts are kept at.
+    pub cell_size: f32,
+    /// Where drift seeds come to rest once they have fully let go of their dancers.
+    pub settle: [f32; 3],
+}
+
+impl Default for WindConfig {
+    fn default() -> Self {
+        Self {
+            steady: [0.4, 0.05, 0.0],
+            turbulence: 0.15,
+            turbulence_scale: 1.5,
+            turbulence_speed: 0.2,
+            gust_strength: 0.8,
+            gust_radius: 0.4,
+            gust_decay: 1.0,
+            cell_size: 0.2,
+            settle: [10.0, -1.0, -2.0],
+        }
+    }
+}
+
+/// Seeds flying as rigid bodies instead of easing after their targets, see
+/// `flight::Flight`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlightConfig {
+    pub enabled: bool,
+    /// Of one seed, in milligrams.
+    pub mass: f32,
+    /// Force of the air on the pappus per unit of speed through it, in milligrams per
+    /// second. A seed falls at `mass * 9.81 / drag` meters per second in still air.
+    pub drag: f32,
+    /// How hard the air flowing through the pappus turns it fluff up, per second squared.
+    pub righting: f32,
+    /// How quickly the seed stops tumbling, per second.
+    pub angular_damping: f32,
+    /// Radians the seed turns about its stem per meter of air through the pappus.
+    pub spin: f32,
+    /// Pull towards a seed's dancer per meter away, per second squared.
+    pub steering: f32,
+}
+
+impl Default for FlightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mass: 0.6,
+            drag: 19.6,
+            righting: 40.0,
+            angular_damping: 8.0,
+            spin: 3.0,
+            steering: 200.0,
+        }
+    }
+}
+
+/// The virtual camera, placed and shaped to match the projector, see `camera::Camera`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// In meters, in the same space as the calibrated bodies.
+    pub position: [f32; 3],
+    /// Degrees turned to the right from looking down -Z.
+    pub yaw: f32,
+    /// Degrees tilted up.
+    pub pitch: f32,
+    /// Degrees turned clockwise about the view direction.
+    pub roll: f32,
+    /// Vertical field of view, in degrees.
+    pub fov: f32,
+    /// Distance to the near clipping plane, in meters.
+    pub near: f32,
+    /// Distance to the far clipping plane, in meters.
+    pub far: f32,
+    /// Shift of the image as a fraction of its width and height, like a projector's lens
+    /// shift. `[0, 0.5]` puts the bottom edge of the image level with the camera.
+    pub lens_shift: [f32; 2],
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            fov: 60.0,
+            near: 0.1,
+            far: 100.0,
+            lens_shift: [0.0; 2],
+        }
+    }
+}
+
+/// Where the window opens, e.g. borderless across every projector of a spanned desktop.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Top left corner of the window on the desktop, in points.
+    pub position: Option<[f32; 2]>,
+    /// Inside size of the window, in points.
+    pub size: Option<[f32; 2]>,
+    pub borderless: bool,
+}
+
+/// One projector's share of the window, see `projectors::Projectors`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Part of the window the output covers, as [x, y, width, height] fractions of it
+    /// from the top left.
+    pub viewport: [f32; 4],
+    /// Part of the camera's image the output shows, as [left, bottom, right, top]
+    /// fractions of it.
+    pub frustum: [f32; 4],
+    pub blend: EdgeBlendConfig,
+}
+
+impl OutputConfig {
+    /// Projecting onto nothing, or from nothing, divides by zero.
+    fn check(&self) -> Result<(), &'static str> {
+        let [_, _, width, height] = self.viewport;
+        if width <= 0.0 || height <= 0.0 {
+            return Err("needs a viewport wider and taller than 0");
+        }
+        let [left, bottom, right, top] = self.frustum;
+        if right <= left || top <= bottom {
+            return Err("needs a frustum with right past left and top above bottom");
+        }
+        Ok(())
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            viewport: [0.0, 0.0, 1.0, 1.0],
+            frustum: [0.0, 0.0, 1.0, 1.0],
+            blend: EdgeBlendConfig::default(),
+        }
+    }
+}
+
+/// Soft edges where an output overlaps its neighbours, so the overlap is no brighter than
+/// the rest of the image.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeBlendConfig {
+    /// Width of the ramp at each edge, as a fraction of the output's width or height.
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+    /// Steepness of the ramp: 1 is linear, higher is flatter at both ends.
+    pub curve: f32,
+    /// Of the projector, to undo so the two ramps add up in light rather than in pixels.
+    pub gamma: f32,
+}
+
+impl Default for EdgeBlendConfig {
+    fn default() -> Self {
+        Self {
+            left: 0.0,
+            right: 0.0,
+            top: 0.0,
+            bottom: 0.0,
+            curve: 2.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+/// Projection mapping for the outputs, see `warp::Warp`. Press P in the app to drag the
+/// corners and grid points; the warps are saved to `file` when done, and loaded from it on
+/// the next start.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarpConfig {
+    pub file: PathBuf,
+    /// Control points across and down a new warp.
+    pub grid: [usize; 2],
+}
+
+impl Default for WarpConfig {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("./warp.toml"),
+            grid: [4, 4],
+        }
+    }
+}
+
+/// Effects over each output's image, see `post::PostProcess`. The bloom, grain and
+/// vignette levels of the cues scale the amounts set here.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostConfig {
+    pub bloom: BloomConfig,
+    pub grain: GrainConfig,
+    pub vignette: VignetteConfig,
+    pub tonemap: TonemapConfig,
+}
+
+/// A glow around whatever is brighter than `threshold`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BloomConfig {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Blur passes at half size, each widening the glow.
+    pub passes: usize,
+    /// Spread of each blur pass, in half size pixels.
+    pub radius: f32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.6,
+            intensity: 0.8,
+            passes: 4,
+            radius: 1.5,
+        }
+    }
+}
+
+/// Flickering noise over the whole image.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrainConfig {
+    pub enabled: bool,
+    pub amount: f32,
+}
+
+impl Default for GrainConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            amount: 0.04,
+        }
+    }
+}
+
+/// Darkening towards the corners.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VignetteConfig {
+    pub enabled: bool,
+    /// How dark the corners get, 1 is black.
+    pub amount: f32,
+    /// How far in from the corners the darkening starts, as a fraction of the way to the
+    /// middle.
+    pub softness: f32,
+}
+
+impl Default for VignetteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            amount: 0.4,
+            softness: 0.6,
+        }
+    }
+}
+
+/// Brings the high dynamic range image down to what the projector can show, rolling off
+/// the highlights instead of clipping them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TonemapConfig {
+    pub enabled: bool,
+    pub exposure: f32,
+}
+
+impl Default for TonemapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            exposure: 1.0,
+        }
+    }
+}
+
+/// Rendering to PNG frames without a window, see `render::render`. The bodies come from
+/// the `[replay]` recording and any mock sources, and the cues are called at the `go`
+/// times.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    /// Directory the frames are written to. Rendering only happens when it is set.
+    pub output: Option<PathBuf>,
+    /// Width and height of the frames, in pixels.
+    pub size: [u32; 2],
+    /// Frames per second of simulated time.
+    pub fps: f32,
+    /// Seconds to render, by default the length of the recording.
+    pub duration: Option<f32>,
+    /// Seconds from the start at which GO is pressed, one cue each.
+    pub go: Vec<f32>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            output: None,
+            size: [1920, 1080],
+            fps: 30.0,
+            duration: None,
+            go: Vec::new(),
+        }
+    }
+}
+
+/// Where the randomness in the scene and the mock starts from. The same seed and the same
+/// bodies and cues give the same frames, see `render::render`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomConfig {
+    pub seed: u64,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            looping: true,
+            stream: None,
+            sample_rate: 48000,
+            channels: 2,
+            mappings: Vec::new(),
+        }
+    }
+}
+
+/// Maps an OSC address pattern such as `/body{body}/spine_mid` to a joint. The body and
+/// joint may be left out when the pattern captures them with `{body}` and `{joint}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JointMapping {
+    pub pattern: String,
+    pub body: Option<usize>,
+    pub joint: Option<String>,
+}
+
+/// Where the sensor-to-stage transform is stored, and the stage marks a dancer stands on
+/// while it is being measured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    pub file: PathBuf,
+    pub body: usize,
+    pub joint: String,
+    /// Stage coordinates, in meters, where `joint` should end up at each mark.
+    pub points: Vec<[f32; 3]>,
+}
+
+/// The filter chain every joint goes through on its way from the tracker to the scene,
+/// with per-joint overrides keyed by joint name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiltersConfig {
+    pub default: Vec<FilterConfig>,
+    pub joints: HashMap<String, Vec<FilterConfig>>,
+    /// Seconds a joint the filters have no estimate for keeps its last known position
+    /// before its body counts as lost.
+    pub forget_after: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FilterConfig {
+    OneEuro {
+        min_cutoff: f32,
+        beta: f32,
+        d_cutoff: f32,
+    },
+    ConstantVelocity {
+        process_noise: f32,
+        measurement_noise: f32,
+        max_prediction: f32,
+    },
+    Outlier {
+        max_speed: f32,
+        reset_after: f32,
+    },
+    Hold {
+        timeout: f32,
+    },
+}
+
+/// How the scene treats a dancer whose body stops arriving from the tracker.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingConfig {
+    /// Seconds without any joint before a body counts as lost.
+    pub lost_after: f32,
+    /// Seconds a seed takes to leave when its body is lost, and to come back.
+    pub transition: f32,
+    pub behavior: LostBehavior,
+    /// Where seeds rest with `return_home`.
+    pub home: [f32; 3],
+    /// Where seeds float off to with `drift_away`.
+    pub away: [f32; 3],
+    /// Lets the seeds change dancers when the dancers cross, see `Scene::check_dandelions`.
+    pub swap_seeds: bool,
+    /// How far, in meters, a seed must have strayed from its dancer before it may swap.
+    pub swap_distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LostBehavior {
+    Hold,
+    FadeOut,
+    DriftAway,
+    ReturnHome,
+}
+
+/// What a `mock` source does: a random walk, or the script in `script`, see
+/// `choreography::Choreography`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockConfig {
+    pub script: Option<PathBuf>,
+    /// Playback rate, 2.0 moves twice as fast.
+    pub speed: f32,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            script: None,
+            speed: 1.0,
+        }
+    }
+}
+
+/// The show's cue list, see `cues::Cue`. Without a file the built-in list is used.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CuesConfig {
+    pub file: Option<PathBuf>,
+}
+
+/// The recording played back by a `replay` source.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    pub file: Option<PathBuf>,
+    /// Playback rate in `realtime` mode, 2.0 plays twice as fast.
+    pub speed: f64,
+    pub mode: ReplayMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayMode {
+    Realtime,
+    /// Advances one recorded frame each time `.` is pressed.
+    Step,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            osc: OscConfig::default(),
+            calibration: CalibrationConfig::default(),
+            filters: FiltersConfig::default(),
+            tracking: TrackingConfig::default(),
+            replay: ReplayConfig::default(),
+            // filled in by `resolve_sources`
+            sources: Vec::new(),
+            mock: MockConfig::default(),
+            cues: CuesConfig::default(),
+            osc_out: OscOutputConfig::default(),
+            audio: AudioConfig::default(),
+            seeds: vec![
+                SeedBehavior::Follow { body: 1 },
+                SeedBehavior::Drift { body: 2 },
+                SeedBehavior::Dance,
+                SeedBehavior::Dance,
+            ],
+            seed_head: SeedHeadConfig::default(),
+            wind: WindConfig::default(),
+            flight: FlightConfig::default(),
+            camera: CameraConfig::default(),
+            window: WindowConfig::default(),
+            outputs: Vec::new(),
+            warp: WarpConfig::default(),
+            post: PostConfig::default(),
+            render: RenderConfig::default(),
+            random: RandomConfig::default(),
+        }
+    }
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            speed: 1.0,
+            mode: ReplayMode::Realtime,
+        }
+    }
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            lost_after: 0.5,
+            transition: 2.0,
+            behavior: LostBehavior::FadeOut,
+            home: [0.0, 0.0, -2.0],
+            away: [10.0, -1.0, -2.0],
+            swap_seeds: false,
+            swap_distance: 1.0,
+        }
+    }
+}
+
+impl Default for FiltersConfig {
+    fn default() -> Self {
+        Self {
+            default: vec![
+                FilterConfig::Outlier { max_speed: 8.0, reset_after: 0.5 },
+                FilterConfig::OneEuro { min_cutoff: 1.0, beta: 0.5, d_cutoff: 1.0 },
+                FilterConfig::Hold { timeout: 1.0 },
+            ],
+            joints: HashMap::new(),
+            forget_after: 2.0,
+        }
+    }
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("./calibration.toml"),
+            body: 1,
+            joint: "SpineMid".to_string(),
+            points: vec![
+                [-1.0, 0.0, -1.5],
+                [1.0, 0.0, -1.5],
+                [1.0, 0.0, -3.0],
+                [-1.0, 0.0, -3.0],
+            ],
+        }
+    }
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            address: Ipv4Addr::LOCALHOST,
+            port: 9000,
+            joints: vec![
+                JointMapping { pattern: "/joint/body{body}/{joint}".to_string(), body: None, joint: None },
+                JointMapping { pattern: "/body{body}/{joint}".to_string(), body: None, joint: None },
+            ],
+            record: None,
+            control: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config {}: {}", path.display(), e))?;
+        let config: Self = toml::from_str(&text)
+            .map_err(|e| format!("Cannot parse config {}: {}", path.display(), e))?;
+        for (i, output) in config.outputs.iter().enumerate() {
+            output.check().map_err(|e| format!("Cannot use config {}: output {} {}", path.display(), i + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Loads `--config <file>` (or `./dandelions.toml` if it exists) and applies the
+    /// remaining command-line overrides on top of it.
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let args = args.skip(1).collect::<Vec<_>>();
+        if args.len() % 2 != 0 {
+            return Err(format!("missing value for {}\n{}", args[args.len() - 1], USAGE).into());
+        }
+        let pairs = args.chunks(2).map(|pair| (pair[0].as_str(), pair[1].as_str())).collect::<Vec<_>>();
+
+        let mut config = match pairs.iter().find(|(arg, _)| *arg == "--config") {
+            Some((_, path)) => Self::from_file(Path::new(path))?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => Self::default(),
+        };
+        for (arg, value) in pairs {
+            config.apply_arg(arg, value)
+                .map_err(|e| format!("invalid {} {}: {}\n{}", arg, value, e, USAGE))?;
+        }
+        config.resolve_sources()?;
+        Ok(config)
+    }
+
+    /// Without any sources the tracker drives every body and the mock dances bodies 1 and 2,
+    /// as before sources could be chosen. A recording given with `--replay` plays in place
+    /// of the mock, or takes every unlisted body when there is no mock.
+    fn resolve_sources(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.sources.is_empty() {
+            self.sources = vec![
+                SourceConfig { kind: SourceKind::Osc, bodies: None },
+                SourceConfig { kind: SourceKind::Mock, bodies: Some(vec![1, 2]) },
+            ];
+        }
+        if self.replay.file.is_none() || self.sources.iter().any(|source| source.kind == SourceKind::Replay) {
+            return Ok(());
+        }
+        let catch_all = self.sources.iter().any(|source| source.bodies.is_none());
+        match self.sources.iter_mut().find(|source| source.kind == SourceKind::Mock) {
+            Some(mock) => mock.kind = SourceKind::Replay,
+            None if !catch_all => self.sources.push(SourceConfig { kind: SourceKind::Replay, bodies: None }),
+            None => return Err("nothing to replay on: every body not listed already has a source, give the replay source its bodies with --sources".into()),
+        }
+        Ok(())
+    }
+
+    fn apply_arg(&mut self, arg: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match arg {
+            "--config" => {}
+            "--osc-address" => self.osc.address = value.parse()?,
+            "--osc-port" => self.osc.port = value.parse()?,
+            "--calibration" => self.calibration.file = PathBuf::from(value),
+            "--warp" => self.warp.file = PathBuf::from(value),
+            "--record" => self.osc.record = Some(PathBuf::from(value)),
+            "--replay" => self.replay.file = Some(PathBuf::from(value)),
+            "--replay-speed" => self.replay.speed = value.parse()?,
+            "--replay-mode" => self.replay.mode = match value {
+                "realtime" => ReplayMode::Realtime,
+                "step" => ReplayMode::Step,
+                _ => return Err("expected realtime or step".into()),
+            },
+            "--mock-script" => self.mock.script = Some(PathBuf::from(value)),
+            "--mock-speed" => self.mock.speed = value.parse()?,
+            "--cues" => self.cues.file = Some(PathBuf::from(value)),
+            "--osc-out" => self.osc_out.destination = Some(value.parse()?),
+            "--osc-out-rate" => self.osc_out.rate = value.parse()?,
+            "--audio" => self.audio.file = Some(PathBuf::from(value)),
+            "--audio-stream" => self.audio.stream = Some(PathBuf::from(value)),
+            "--render" => self.render.output = Some(PathBuf::from(value)),
+            "--render-size" => {
+                let (width, height) = value.split_once('x').ok_or("expected <width>x<height>")?;
+                self.render.size = [width.parse()?, height.parse()?];
+            }
+            "--render-fps" => self.render.fps = value.parse()?,
+            "--render-duration" => self.render.duration = Some(value.parse()?),
+            "--seed" => self.random.seed = value.parse()?,
+            "--sources" => self.sources = value.split(',').map(str::parse).collect::<Result<_, _>>()?,
+            _ => return Err("unknown argument".into()),
+        }
+        Ok(())
+    }
+}
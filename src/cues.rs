@@ -0,0 +1,430 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::transition::{Curve, Transition};
+use crate::DandelionState;
+
+/// The show levels a cue can set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parameter {
+    Brightness,
+    Affection,
+    DancingBrightness,
+    Drift,
+    /// How much of the configured bloom, grain and vignette is on, see `post::PostProcess`.
+    Bloom,
+    Grain,
+    Vignette,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 7] = [
+        Parameter::Brightness, Parameter::Affection, Parameter::DancingBrightness, Parameter::Drift,
+        Parameter::Bloom, Parameter::Grain, Parameter::Vignette,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// As in cue lists and OSC messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::Brightness => "brightness",
+            Parameter::Affection => "affection",
+            Parameter::DancingBrightness => "dancing_brightness",
+            Parameter::Drift => "drift",
+            Parameter::Bloom => "bloom",
+            Parameter::Grain => "grain",
+            Parameter::Vignette => "vignette",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Parameter> {
+        Parameter::ALL.into_iter().find(|parameter| parameter.name() == name)
+    }
+
+    /// The level before any cue sets it: the scene starts dark and still, and the effects
+    /// at the strength configured for them.
+    fn rest(self) -> f32 {
+        match self {
+            Parameter::Bloom | Parameter::Grain | Parameter::Vignette => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// A remote control command for the show, see `kinect_tracker::handle_control_msg`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShowCommand {
+    Go,
+    Back,
+    /// Goes to the first cue.
+    Start,
+    /// Fades back to standby, before the first cue.
+    Stop,
+    /// Jumps to a cue by number, counting from 1.
+    Cue(usize),
+    CueNamed(String),
+    /// Fades a level to `value` over `fade` seconds.
+    Set {
+        parameter: Parameter,
+        value: f32,
+        fade: f32,
+    },
+    Trigger(Trigger),
+}
+
+/// Something that happens once when a cue fires, rather than a level that fades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The seed head lets go of its seeds.
+    Release,
+    /// The seeds go back on the seed head.
+    Regrow,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CueFile {
+    cues: Vec<Cue>,
+}
+
+/// One step of the show. Levels a cue leaves out keep the value earlier cues gave them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cue {
+    pub name: String,
+    pub brightness: Option<f32>,
+    pub affection: Option<f32>,
+    pub dancing_brightness: Option<f32>,
+    pub drift: Option<f32>,
+    pub bloom: Option<f32>,
+    pub grain: Option<f32>,
+    pub vignette: Option<f32>,
+    /// Seconds to fade to the new levels.
+    #[serde(default = "default_fade")]
+    pub fade: f32,
+    #[serde(default)]
+    pub curve: Curve,
+    /// Releases the seed head. Going back before this cue regrows it.
+    #[serde(default)]
+    pub release: bool,
+}
+
+fn default_fade() -> f32 {
+    3.0
+}
+
+impl Cue {
+    fn target(&self, parameter: Parameter) -> Option<f32> {
+        match parameter {
+            Parameter::Brightness => self.brightness,
+            Parameter::Affection => self.affection,
+            Parameter::DancingBrightness => self.dancing_brightness,
+            Parameter::Drift => self.drift,
+            Parameter::Bloom => self.bloom,
+            Parameter::Grain => self.grain,
+            Parameter::Vignette => self.vignette,
+        }
+    }
+
+    fn new(name: &str, fade: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            brightness: None,
+            affection: None,
+            dancing_brightness: None,
+            drift: None,
+            bloom: None,
+            grain: None,
+            vignette: None,
+            fade,
+            curve: Curve::Linear,
+            release: false,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Cue>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read cue list {}: {}", path.display(), e))?;
+    let file: CueFile = toml::from_str(&text)
+        .map_err(|e| format!("Cannot parse cue list {}: {}", path.display(), e))?;
+    if file.cues.is_empty() {
+        return Err(format!("cue list {} has no cues", path.display()).into());
+    }
+    for cue in &file.cues {
+        if cue.fade < 0.0 {
+            return Err(format!("cue {} in {} has a negative fade", cue.name, path.display()).into());
+        }
+        for parameter in Parameter::ALL {
+            if let Some(level) = cue.target(parameter).filter(|level| !(0.0..=1.0).contains(level)) {
+                return Err(format!("cue {} in {} sets {} to {}, levels go from 0 to 1", cue.name, path.display(), parameter.name(), level).into());
+            }
+        }
+    }
+    Ok(file.cues)
+}
+
+/// The show as it used to run from the Space, 3 and 5 keys.
+pub fn default_cues() -> Vec<Cue> {
+    vec![
+        Cue { brightness: Some(1.0), ..Cue::new("Start", 1.7) },
+        Cue { brightness: Some(0.0), dancing_brightness: Some(1.0), ..Cue::new("Dance", 1.7) },
+        Cue { brightness: Some(1.0), dancing_brightness: Some(0.0), ..Cue::new("Dance out", 1.7) },
+        Cue { drift: Some(1.0), ..Cue::new("Drift", 167.0) },
+        Cue { brightness: Some(0.0), ..Cue::new("End", 1.7) },
+    ]
+}
+
+/// Steps through the cue list, fading every level towards what the current cue calls for.
+/// Before the first cue every level is at rest, see `Parameter::rest`.
+pub struct CuePlayer {
+    cues: Vec<Cue>,
+    current: Option<usize>,
+    levels: [Transition; Parameter::ALL.len()],
+    /// Cue number typed in for a jump.
+    jump: String,
+    /// Triggers fired since the scene last took them.
+    triggers: Vec<Trigger>,
+}
+
+impl CuePlayer {
+    pub fn new(cues: Vec<Cue>) -> Self {
+        let now = Instant::now();
+        Self {
+            cues,
+            current: None,
+            levels: Parameter::ALL.map(|parameter| Transition::constant(parameter.rest(), now)),
+            jump: String::new(),
+            triggers: Vec::new(),
+        }
+    }
+
+    pub fn go(&mut self, now: Instant) {
+        let next = self.current.map_or(0, |current| current + 1);
+        if next < self.cues.len() {
+            self.fire(Some(next), next, now);
+        }
+    }
+
+    /// Returns to the previous cue, fading back as long as the cue being left took.
+    pub fn back(&mut self, now: Instant) {
+        if let Some(current) = self.current {
+            self.fire(current.checked_sub(1), current, now);
+        }
+    }
+
+    pub fn jump(&mut self, index: usize, now: Instant) -> Result<(), String> {
+        if index >= self.cues.len() {
+            return Err(format!("there is no cue {}", index + 1));
+        }
+        self.fire(Some(index), index, now);
+        Ok(())
+    }
+
+    /// Digits build up a cue number, G jumps to it.
+    pub fn type_jump(&mut self, digit: char) {
+        self.jump.push(digit);
+    }
+
+    pub fn confirm_jump(&mut self, now: Instant) -> Result<(), String> {
+        let typed = std::mem::take(&mut self.jump);
+        match typed.parse::<usize>() {
+            Ok(number) if number > 0 => self.jump(number - 1, now),
+            _ => Err("type a cue number before G".to_string()),
+        }
+    }
+
+    pub fn handle(&mut self, command: ShowCommand, now: Instant) -> Result<(), String> {
+        match command {
+            ShowCommand::Go => self.go(now),
+            ShowCommand::Back => self.back(now),
+            ShowCommand::Start => self.jump(0, now)?,
+            ShowCommand::Stop => {
+                if let Some(current) = self.current {
+                    self.fire(None, current, now);
+                }
+            }
+            ShowCommand::Cue(number) => self.jump(number.checked_sub(1).ok_or("there is no cue 0")?, now)?,
+            ShowCommand::CueNamed(name) => {
+                let index = self.cues.iter().position(|cue| cue.name == name)
+                    .ok_or_else(|| format!("there is no cue named {}", name))?;
+                self.jump(index, now)?;
+            }
+            ShowCommand::Set { parameter, value, fade } => {
+                self.levels[parameter.index()].fade_to(value.clamp(0.0, 1.0), fade.max(0.0), Curve::EaseInOut, now);
+            }
+            ShowCommand::Trigger(trigger) => self.triggers.push(trigger),
+        }
+        Ok(())
+    }
+
+    /// Moves a level by hand, stopping any fade it was in.
+    pub fn nudge(&mut self, parameter: Parameter, amount: f32, now: Instant) {
+        let level = &mut self.levels[parameter.index()];
+        let value = (level.value(now) + amount).clamp(0.0, 1.0);
+        *level = Transition::constant(value, now);
+    }
+
+    /// The triggers fired since the last call.
+    pub fn take_triggers(&mut self) -> Vec<Trigger> {
+        std::mem::take(&mut self.triggers)
+    }
+
+    pub fn state(&self, now: Instant) -> DandelionState {
+        let level = |parameter: Parameter| self.levels[parameter.index()].value(now);
+        DandelionState {
+            brightness: level(Parameter::Brightness),
+            affection: level(Parameter::Affection),
+            dancing_brightness: level(Parameter::DancingBrightness),
+            drift_strength: level(Parameter::Drift),
+            bloom: level(Parameter::Bloom),
+            grain: level(Parameter::Grain),
+            vignette: level(Parameter::Vignette),
+        }
+    }
+
+    pub fn status(&self, now: Instant) -> String {
+        let mut status = match self.current {
+            Some(current) => {
+                let progress = self.levels.iter().map(|level| level.progress(now)).fold(1.0f32, f32::min);
+                let mut status = format!("Cue {}/{}: {}", current + 1, self.cues.len(), self.cues[current].name);
+                if progress < 1.0 {
+                    status += &format!(" ({:.0}%)", progress * 100.0);
+                }
+                status
+            }
+            None => format!("Standby, {} cues", self.cues.len()),
+        };
+        let next = self.current.map_or(0, |current| current + 1);
+        if let Some(cue) = self.cues.get(next) {
+            status += &format!("\nNext: {}", cue.name);
+        }
+        if !self.jump.is_empty() {
+            status += &format!("\nJump to {}_", self.jump);
+        }
+        status
+    }
+
+    /// Fades to the levels cue `index` tracks to, taking the time and curve of `timing`.
+    fn fire(&mut self, index: Option<usize>, timing: usize, now: Instant) {
+        let timing = &self.cues[timing];
+        let cues_up_to = |index: Option<usize>| match index {
+            Some(index) => &self.cues[..=index],
+            None => &[],
+        };
+        // the seed head tracks like a level: released once any cue so far has released it
+        let released = |index| cues_up_to(index).iter().any(|cue| cue.release);
+        match (released(self.current), released(index)) {
+            (false, true) => self.triggers.push(Trigger::Release),
+            (true, false) => self.triggers.push(Trigger::Regrow),
+            _ => {}
+        }
+        for parameter in Parameter::ALL {
+            let cues = cues_up_to(index);
+            let target = cues.iter().rev().find_map(|cue| cue.target(parameter)).unwrap_or(parameter.rest());
+            let level = &mut self.levels[parameter.index()];
+            if level.target() != target {
+                level.fade_to(target, timing.fade, timing.curve, now);
+            }
+        }
+        self.current = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn later(now: Instant) -> Instant {
+        now + Duration::from_secs(1000)
+    }
+
+    #[test]
+    fn starts_at_rest() {
+        let player = CuePlayer::new(default_cues());
+        let state = player.state(Instant::now());
+        assert_eq!(state.brightness, 0.0);
+        assert_eq!(state.drift_strength, 0.0);
+        assert_eq!(state.bloom, 1.0);
+    }
+
+    #[test]
+    fn go_tracks_levels_from_earlier_cues() {
+        let mut player = CuePlayer::new(default_cues());
+        let now = Instant::now();
+        player.go(now);
+        assert_eq!(player.state(later(now)).brightness, 1.0);
+        player.go(now);
+        let state = player.state(later(now));
+        assert_eq!((state.brightness, state.dancing_brightness), (0.0, 1.0));
+        player.jump(3, now).unwrap();
+        let state = player.state(later(now));
+        assert_eq!((state.brightness, state.dancing_brightness, state.drift_strength), (1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn go_stops_at_the_last_cue() {
+        let mut player = CuePlayer::new(default_cues());
+        let now = Instant::now();
+        for _ in 0..10 {
+            player.go(now);
+        }
+        assert!(player.status(now).starts_with("Cue 5/5: End"));
+    }
+
+    #[test]
+    fn back_returns_to_standby() {
+        let mut player = CuePlayer::new(default_cues());
+        let now = Instant::now();
+        player.go(now);
+        player.back(now);
+        assert!(player.status(now).starts_with("Standby"));
+        assert_eq!(player.state(later(now)).brightness, 0.0);
+    }
+
+    #[test]
+    fn release_and_regrow_follow_the_cues() {
+        let cues = vec![Cue::new("Still", 0.0), Cue { release: true, ..Cue::new("Blow", 0.0) }, Cue::new("After", 0.0)];
+        let mut player = CuePlayer::new(cues);
+        let now = Instant::now();
+        player.go(now);
+        assert!(player.take_triggers().is_empty());
+        player.jump(2, now).unwrap();
+        assert_eq!(player.take_triggers(), vec![Trigger::Release]);
+        player.back(now);
+        assert!(player.take_triggers().is_empty());
+        player.back(now);
+        assert_eq!(player.take_triggers(), vec![Trigger::Regrow]);
+    }
+
+    #[test]
+    fn jumps_by_typed_number_or_name() {
+        let mut player = CuePlayer::new(default_cues());
+        let now = Instant::now();
+        player.type_jump('4');
+        player.confirm_jump(now).unwrap();
+        assert!(player.status(now).starts_with("Cue 4/5: Drift"));
+        assert!(player.confirm_jump(now).is_err());
+        player.handle(ShowCommand::CueNamed("Dance".to_string()), now).unwrap();
+        assert!(player.status(now).starts_with("Cue 2/5: Dance"));
+        assert!(player.handle(ShowCommand::Cue(0), now).is_err());
+        assert!(player.handle(ShowCommand::Cue(6), now).is_err());
+    }
+
+    #[test]
+    fn load_rejects_levels_outside_zero_to_one() {
+        let path = std::env::temp_dir().join(format!("dandelions-cues-{}.toml", std::process::id()));
+        std::fs::write(&path, "[[cues]]\nname = \"Glare\"\nbrightness = 3.0\n").unwrap();
+        let error = load(&path).unwrap_err().to_string();
+        std::fs::write(&path, "[[cues]]\nname = \"Glow\"\nbrightness = 1.0\n").unwrap();
+        let loaded = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("cue Glare") && error.contains("brightness"), "{}", error);
+        assert_eq!(loaded.unwrap()[0].brightness, Some(1.0));
+    }
+}
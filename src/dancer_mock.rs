@@ -1,75 +1,149 @@
-use rand::rngs::OsRng;
-use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
 
-use crate::{BODY1_BASE_SPINE, BODY1_HEAD, BODY2_BASE_SPINE, BODY2_HEAD};
+use crate::body_source::{BodySink, BodySource};
+use crate::choreography::Choreography;
+use crate::config::MockConfig;
+use crate::skeleton::{Joint, JointUpdate};
 
-pub fn spawn_dancer_mock() -> Result<(), Box<dyn std::error::Error>> {
-    std::thread::spawn(move || {
-        let mut rng = OsRng::default();
-        loop {
-            let mut cur_pos = BODY1_BASE_SPINE.lock();
-            let mut rand_dir = [0.0, 0.0, 0.0];
-            if cur_pos[0] > 1.0 {
-                rand_dir[0] = rng.gen_range(-1.0..0.0);
-            } else if cur_pos[0] < -1.0 {
-                rand_dir[0] = rng.gen_range(0.0..1.0);
-            } else {
-                rand_dir[0] = rng.gen_range(-1.0..1.0);
-            }
-            if cur_pos[1] > 0.2 {
-                rand_dir[1] = rng.gen_range(-0.2..0.2);
-            } else if cur_pos[1] < -0.2 {
-                rand_dir[1] = rng.gen_range(0.0..0.2);
-            } else {
-                rand_dir[1] = rng.gen_range(-0.2..0.2);
-            }
-            if cur_pos[2] < -3.0 {
-                rand_dir[2] = rng.gen_range(0.0..1.0);
-            } else if cur_pos[2] > -1.0 {
-                rand_dir[2] = rng.gen_range(-1.0..0.0);
-            } else {
-                rand_dir[2] = rng.gen_range(-1.0..1.0);
-            }
-            cur_pos[0] = cur_pos[0] + rand_dir[0] * 0.01;
-            cur_pos[1] = cur_pos[1] + rand_dir[1] * 0.01;
-            cur_pos[2] = cur_pos[2] + rand_dir[2] * 0.01;
-            let body_pos = *cur_pos;
-            std::mem::drop(cur_pos);
-            let mut head_pos = BODY1_HEAD.lock();
-            *head_pos = [body_pos[0], body_pos[1] + 0.2, body_pos[2]];
+/// The bodies the mock animates when its source is not given any.
+const MOCK_BODIES: [usize; 2] = [1, 2];
+/// Time the dancers move on by at each step.
+const STEP: Duration = Duration::from_millis(10);
 
-            let mut cur_pos = BODY2_BASE_SPINE.lock();
-            let mut rand_dir = [0.0, 0.0, 0.0];
-            if cur_pos[0] > 1.0 {
-                rand_dir[0] = rng.gen_range(-1.0..0.0);
-            } else if cur_pos[0] < -1.0 {
-                rand_dir[0] = rng.gen_range(0.0..1.0);
-            } else {
-                rand_dir[0] = rng.gen_range(-1.0..1.0);
-            }
-            if cur_pos[1] > 0.2 {
-                rand_dir[1] = rng.gen_range(-0.2..0.2);
-            } else if cur_pos[1] < -0.2 {
-                rand_dir[1] = rng.gen_range(0.0..0.2);
-            } else {
-                rand_dir[1] = rng.gen_range(-0.2..0.2);
+/// Dancers following a script, or wandering randomly around the middle of the stage
+/// without one.
+pub struct MockSource {
+    script: Option<Choreography>,
+    speed: f32,
+    seed: u64,
+}
+
+impl MockSource {
+    pub fn new(config: &MockConfig, seed: u64) -> Result<Self, Box<dyn std::error::Error>> {
+        if config.speed <= 0.0 {
+            return Err(format!("mock speed must be positive, not {}", config.speed).into());
+        }
+        let script = match &config.script {
+            Some(path) => {
+                let script = Choreography::load(path)?;
+                println!("Playing mock script {} ({} bodies)", path.display(), script.num_bodies());
+                Some(script)
             }
-            if cur_pos[2] < -3.0 {
-                rand_dir[2] = rng.gen_range(0.0..1.0);
-            } else if cur_pos[2] > -1.0 {
-                rand_dir[2] = rng.gen_range(-1.0..0.0);
-            } else {
-                rand_dir[2] = rng.gen_range(-1.0..1.0);
+            None => None,
+        };
+        Ok(Self {
+            script,
+            speed: config.speed,
+            seed,
+        })
+    }
+}
+
+impl BodySource for MockSource {
+    fn start(self: Box<Self>, sink: BodySink) -> Result<(), Box<dyn std::error::Error>> {
+        let mut dancers = MockDancers::new(*self, &sink, Instant::now());
+        std::thread::spawn(move || loop {
+            dancers.advance(&sink, Instant::now());
+            std::thread::sleep(STEP);
+        });
+        Ok(())
+    }
+}
+
+/// The mock's dancers, moved on in steps of `STEP` from their start, so that where they
+/// are at any time depends only on the seed and not on when they are looked at.
+pub struct MockDancers {
+    script: Option<Choreography>,
+    speed: f32,
+    /// The randomly wandering bodies, each with its own random numbers.
+    walkers: Vec<Walker>,
+    start: Instant,
+    steps: u32,
+}
+
+struct Walker {
+    body: usize,
+    rng: ChaCha8Rng,
+    position: [f32; 3],
+}
+
+impl MockDancers {
+    pub fn new(source: MockSource, sink: &BodySink, start: Instant) -> Self {
+        let bodies = match sink.bodies() {
+            Some(bodies) => bodies.to_vec(),
+            None => MOCK_BODIES.iter().copied().filter(|body| sink.accepts(*body)).collect(),
+        };
+        let walkers = bodies.into_iter()
+            .map(|body| Walker {
+                body,
+                // spread over the seeds so no two bodies or seeds walk alike
+                rng: ChaCha8Rng::seed_from_u64(source.seed ^ (body as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+                position: [0.0; 3],
+            })
+            .collect();
+        Self {
+            script: source.script,
+            speed: source.speed,
+            walkers,
+            start,
+            steps: 0,
+        }
+    }
+
+    /// Takes every step due by `now`, delivering each into `sink` at the time it was due.
+    pub fn advance(&mut self, sink: &BodySink, now: Instant) {
+        loop {
+            let due = self.start + STEP * (self.steps + 1);
+            if due > now {
+                return;
             }
-            cur_pos[0] = cur_pos[0] + rand_dir[0] * 0.01;
-            cur_pos[1] = cur_pos[1] + rand_dir[1] * 0.01;
-            cur_pos[2] = cur_pos[2] + rand_dir[2] * 0.01;
-            let body_pos = *cur_pos;
-            std::mem::drop(cur_pos);
-            let mut head_pos = BODY2_HEAD.lock();
-            *head_pos = [body_pos[0], body_pos[1] + 0.2, body_pos[2]];
-            std::thread::sleep(std::time::Duration::from_millis(10));
+            self.steps += 1;
+            let updates = self.step();
+            sink.apply(&updates, due);
         }
-    });
-    Ok(())
-}
\ No newline at end of file
+    }
+
+    fn step(&mut self) -> Vec<JointUpdate> {
+        if let Some(script) = self.script.as_mut() {
+            return script.step(STEP.as_secs_f32() * self.speed);
+        }
+        let mut updates = Vec::with_capacity(self.walkers.len() * 2);
+        for walker in &mut self.walkers {
+            step_body(&mut walker.rng, &mut walker.position, self.speed);
+            let [x, y, z] = walker.position;
+            updates.push(JointUpdate { body: walker.body, joint: Joint::SpineMid, position: walker.position });
+            updates.push(JointUpdate { body: walker.body, joint: Joint::Head, position: [x, y + 0.2, z] });
+        }
+        updates
+    }
+}
+
+fn step_body(rng: &mut ChaCha8Rng, cur_pos: &mut [f32; 3], speed: f32) {
+    let mut rand_dir = [0.0, 0.0, 0.0];
+    if cur_pos[0] > 1.0 {
+        rand_dir[0] = rng.gen_range(-1.0..0.0);
+    } else if cur_pos[0] < -1.0 {
+        rand_dir[0] = rng.gen_range(0.0..1.0);
+    } else {
+        rand_dir[0] = rng.gen_range(-1.0..1.0);
+    }
+    if cur_pos[1] > 0.2 {
+        rand_dir[1] = rng.gen_range(-0.2..0.2);
+    } else if cur_pos[1] < -0.2 {
+        rand_dir[1] = rng.gen_range(0.0..0.2);
+    } else {
+        rand_dir[1] = rng.gen_range(-0.2..0.2);
+    }
+    if cur_pos[2] < -3.0 {
+        rand_dir[2] = rng.gen_range(0.0..1.0);
+    } else if cur_pos[2] > -1.0 {
+        rand_dir[2] = rng.gen_range(-1.0..0.0);
+    } else {
+        rand_dir[2] = rng.gen_range(-1.0..1.0);
+    }
+    cur_pos[0] += rand_dir[0] * 0.01 * speed;
+    cur_pos[1] += rand_dir[1] * 0.01 * speed;
+    cur_pos[2] += rand_dir[2] * 0.01 * speed;
+}
@@ -1,55 +1,33 @@
-use eframe::egui::accesskit::Affine;
-use eframe::egui::Color32;
 use eframe::{egui_glow, glow::HasContext};
 use egui_glow::glow;
-use tobj;
-use bytemuck;
-use std::time::Instant;
 
 use crate::affine_matrix::AffineMatrix;
 use crate::color::Color;
-use crate::scene::Paintable;
 use crate::obj::{OBJ, VAO};
 use crate::create_program;
 
+/// Floats per seed in an instance buffer: the model matrix, then the color.
+const INSTANCE_FLOATS: usize = 20;
+/// Seeds drawn together, stems then fluff. Only seeds within one slice can blend out of
+/// order, so a slice should be small next to the seeds that overlap on screen.
+const SLICE_SEEDS: usize = 8;
+
+/// Where one seed is and how it looks. Seeds hold no GPU resources of their own, they are
+/// all drawn by a `SeedRenderer`.
 pub struct DandelionSeed {
-    stem_program: glow::Program,
-    fluff_program: glow::Program,
-    fancy_program: glow::Program,
-    fluff_fancy_program: glow::Program,
-    stem_vao: VAO,
-    fluff_vao: VAO,
-    stem_fancy_vao: VAO,
-    fluff_fancy_vao: VAO,
     pub translation: AffineMatrix,
     pub rotation: AffineMatrix,
     pub scale: AffineMatrix,
     pub theta: f32,
+    /// Speed of the spin about Y, in radians per second.
     pub theta_delta: f32,
     pub color: Color,
     pub fancy: bool,
 }
 
 impl DandelionSeed {
-    pub fn new(gl: &glow::Context) -> Self {
-        let mut obj = OBJ::new("./DandelionSeed.obj").unwrap();
-        let stem_program = create_program!(include_str!("./shaders/dandelion.vs"), include_str!("./shaders/dandelion.fs"), gl);
-        let fluff_program = create_program!(include_str!("./shaders/dandelion_bristle.vs"), include_str!("./shaders/dandelion_bristle.fs"), gl);
-        let fancy_program = create_program!(include_str!("./shaders/dandelion_fancy.vs"), include_str!("./shaders/dandelion_fancy.fs"), gl);
-        let fluff_fancy_program = create_program!(include_str!("./shaders/dandelion_fancy.vs"), include_str!("./shaders/dandelion_fancy.fs"), gl);
-        let stem_vao = obj["Circle"].into_vao(gl, stem_program).unwrap();
-        let fluff_vao = obj["Mesh"].into_vao(gl, fluff_program).unwrap();
-        let stem_fancy_vao = obj["Circle"].into_vao(gl, fancy_program).unwrap();
-        let fluff_fancy_vao = obj["Mesh"].into_vao(gl, fluff_fancy_program).unwrap();
+    pub fn new() -> Self {
         Self {
-            stem_program,
-            fluff_program,
-            fancy_program,
-            fluff_fancy_program,
-            stem_vao,
-            fluff_vao,
-            stem_fancy_vao,
-            fluff_fancy_vao,
             translation: AffineMatrix::new(),
             rotation: AffineMatrix::new(),
             scale: AffineMatrix::new(),
@@ -64,156 +42,165 @@ impl DandelionSeed {
         [self.translation.matrix[3][0], self.translation.matrix[3][1], self.translation.matrix[3][2]]
     }
 
-    unsafe fn create_program(gl: &glow::Context) -> glow::Program {
-        let program = gl.create_program().expect("Cannot create program");
-        let vertex_shader_source = include_str!("./shaders/dandelion.vs");
-        let fragment_shader_source = include_str!("./shaders/dandelion.fs");
-
-        let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).expect("Cannot create vertex shader");
-        gl.shader_source(vertex_shader, vertex_shader_source);
-        gl.compile_shader(vertex_shader);
-        assert!(gl.get_shader_compile_status(vertex_shader), "Cannot compile vertex shader: {}", gl.get_shader_info_log(vertex_shader));
-
-        let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).expect("Cannot create fragment shader");
-        gl.shader_source(fragment_shader, fragment_shader_source);
-        gl.compile_shader(fragment_shader);
-        assert!(gl.get_shader_compile_status(fragment_shader), "Cannot compile fragment shader: {}", gl.get_shader_info_log(fragment_shader));
-
-        gl.attach_shader(program, vertex_shader);
-        gl.attach_shader(program, fragment_shader);
-
-        gl.link_program(program);
-        assert!(gl.get_program_link_status(program), "Cannot link program: {}", gl.get_program_info_log(program));
-
-        gl.detach_shader(program, vertex_shader);
-        gl.detach_shader(program, fragment_shader);
-        gl.delete_shader(vertex_shader);
-        gl.delete_shader(fragment_shader);
-
-        program
+    /// The unit vector the stem points along.
+    pub fn get_direction(&self) -> [f32; 3] {
+        let direction = self.rotation.transform_point([0.0, 1.0, 0.0]);
+        let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+        if length > 0.0 {
+            direction.map(|v| v / length)
+        } else {
+            [0.0, 1.0, 0.0]
+        }
     }
 
-    unsafe fn initialize_vertices(gl: &glow::Context, program: glow::Program) -> (glow::VertexArray, i32) {
-        
-        let (models, _) = tobj::load_obj("./DandelionSeed.obj", &tobj::GPU_LOAD_OPTIONS).expect("Cannot load DandelionSeed.obj");
-        let stem = &models[0].mesh;
-        let fluff = &models[1].mesh;
-
-        let vertices_combined = stem.positions.iter().chain(fluff.positions.iter()).map(|v| *v).collect::<Vec<f32>>();
-        let indices_combined = stem.indices.iter().map(|i| *i).chain(fluff.indices.iter().map(|i| i + stem.positions.len() as u32)).collect::<Vec<u32>>();
+    /// The seed's entry in an instance buffer.
+    fn instance(&self, data: &mut Vec<f32>) {
+        // scaled first, then rotated, then moved into place
+        let model = self.scale * self.rotation * self.translation;
+        data.extend_from_slice(model.to_uniform());
+        data.extend_from_slice(&[self.color[0], self.color[1], self.color[2], self.color[3]]);
+    }
+}
 
-        let vao = gl.create_vertex_array().expect("Cannot create vertex array");
-        gl.bind_vertex_array(Some(vao));
+impl Default for DandelionSeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let vbo = gl.create_buffer().expect("Cannot create vertex buffer");
-        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-        gl.buffer_data_u8_slice(
-            glow::ARRAY_BUFFER,
-            bytemuck::cast_slice(&vertices_combined),
-            glow::STATIC_DRAW,
-        );
+/// One mesh drawn with one program.
+struct Pass {
+    program: glow::Program,
+    vao: VAO,
+    view_matrix: Option<glow::UniformLocation>,
+    projection: Option<glow::UniformLocation>,
+}
 
-        let ibo = gl.create_buffer().expect("Cannot create index buffer");
-        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ibo));
-        gl.buffer_data_u8_slice(
-            glow::ELEMENT_ARRAY_BUFFER,
-            bytemuck::cast_slice(&indices_combined),
-            glow::STATIC_DRAW,
-        );
+impl Pass {
+    fn new(gl: &glow::Context, obj: &OBJ, mesh: &str, program: glow::Program, instances: glow::Buffer) -> Self {
+        let vao = obj[mesh].into_vao(gl, program).unwrap();
+        unsafe {
+            gl.bind_vertex_array(Some(vao.vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(instances));
+            let stride = (INSTANCE_FLOATS * std::mem::size_of::<f32>()) as i32;
+            // a mat4 attribute takes a location per column
+            if let Some(model_location) = gl.get_attrib_location(program, "model") {
+                for column in 0..4 {
+                    gl.enable_vertex_attrib_array(model_location + column);
+                    gl.vertex_attrib_pointer_f32(model_location + column, 4, glow::FLOAT, false, stride, (column * 4) as i32 * std::mem::size_of::<f32>() as i32);
+                    gl.vertex_attrib_divisor(model_location + column, 1);
+                }
+            }
+            if let Some(color_location) = gl.get_attrib_location(program, "instance_color") {
+                gl.enable_vertex_attrib_array(color_location);
+                gl.vertex_attrib_pointer_f32(color_location, 4, glow::FLOAT, false, stride, 16 * std::mem::size_of::<f32>() as i32);
+                gl.vertex_attrib_divisor(color_location, 1);
+            }
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            Self {
+                program,
+                vao,
+                view_matrix: gl.get_uniform_location(program, "view_matrix"),
+                projection: gl.get_uniform_location(program, "projection"),
+            }
+        }
+    }
 
-        let position_attrib_location = gl.get_attrib_location(program, "position").unwrap();
+    unsafe fn draw(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix, count: usize) {
+        gl.use_program(Some(self.program));
+        gl.uniform_matrix_4_f32_slice(self.view_matrix.as_ref(), false, view_matrix.to_uniform());
+        gl.uniform_matrix_4_f32_slice(self.projection.as_ref(), false, projection.to_uniform());
+        gl.bind_vertex_array(Some(self.vao.vao));
+        gl.draw_elements_instanced(glow::TRIANGLES, self.vao.num_indices, glow::UNSIGNED_INT, 0, count as i32);
+        gl.bind_vertex_array(None);
+    }
+}
 
-        gl.enable_vertex_attrib_array(position_attrib_location);
+/// Draws any number of seeds from one copy of the mesh and programs, with a single
+/// instanced draw call per mesh for the plain seeds and another for the fancy ones.
+pub struct SeedRenderer {
+    stem: Pass,
+    fluff: Pass,
+    fancy_stem: Pass,
+    fancy_fluff: Pass,
+    plain_instances: glow::Buffer,
+    fancy_instances: glow::Buffer,
+}
 
-        gl.vertex_attrib_pointer_f32(position_attrib_location, 3, glow::FLOAT, false, 3 * std::mem::size_of::<f32>() as i32, 0);
+impl SeedRenderer {
+    pub fn new(gl: &glow::Context) -> Self {
+        let obj = OBJ::new("./DandelionSeed.obj").unwrap();
+        let stem_program = create_program!(include_str!("./shaders/dandelion.vs"), include_str!("./shaders/dandelion.fs"), gl);
+        let fluff_program = create_program!(include_str!("./shaders/dandelion_bristle.vs"), include_str!("./shaders/dandelion_bristle.fs"), gl);
+        let fancy_program = create_program!(include_str!("./shaders/dandelion_fancy.vs"), include_str!("./shaders/dandelion_fancy.fs"), gl);
+        let plain_instances = unsafe { gl.create_buffer().expect("Cannot create buffer") };
+        let fancy_instances = unsafe { gl.create_buffer().expect("Cannot create buffer") };
+        Self {
+            stem: Pass::new(gl, &obj, "Circle", stem_program, plain_instances),
+            fluff: Pass::new(gl, &obj, "Mesh", fluff_program, plain_instances),
+            fancy_stem: Pass::new(gl, &obj, "Circle", fancy_program, fancy_instances),
+            fancy_fluff: Pass::new(gl, &obj, "Mesh", fancy_program, fancy_instances),
+            plain_instances,
+            fancy_instances,
+        }
+    }
 
-        gl.bind_vertex_array(None);
+    /// Draws the seeds in the order given, back to front for blending to come out right:
+    /// slice by slice of `SLICE_SEEDS`, the plain ones of a slice before its fancy ones.
+    pub fn paint<'a>(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix, seeds: impl IntoIterator<Item = &'a DandelionSeed>) {
+        self.draw(gl, view_matrix, projection, seeds, true);
+    }
 
-        (vao, indices_combined.len() as i32)
+    /// Draws only the stems of the seeds, without their fluff.
+    pub fn paint_stems<'a>(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix, seeds: impl IntoIterator<Item = &'a DandelionSeed>) {
+        self.draw(gl, view_matrix, projection, seeds, false);
     }
 
-    pub fn destroy(self, gl: &glow::Context) {
-        unsafe {
-            gl.delete_program(self.stem_program);
-            gl.delete_program(self.fluff_program);
-            
-            gl.delete_vertex_array(self.stem_vao.vao);
-            gl.delete_vertex_array(self.fluff_vao.vao);
-            
-            gl.delete_vertex_array(self.stem_fancy_vao.vao);
-            gl.delete_vertex_array(self.fluff_fancy_vao.vao);
+    fn draw<'a>(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix, seeds: impl IntoIterator<Item = &'a DandelionSeed>, fluff: bool) {
+        let seeds: Vec<&DandelionSeed> = seeds.into_iter().collect();
+        for slice in seeds.chunks(SLICE_SEEDS) {
+            self.draw_slice(gl, view_matrix, projection, slice, fluff);
         }
     }
 
-    fn set_transformation_uniforms(&self, gl: &glow::Context, program: glow::Program) {
-        let translation_location = unsafe { gl.get_uniform_location(program, "translation").expect("Cannot get uniform location") };
-        let rotation_location = unsafe { gl.get_uniform_location(program, "rotation").expect("Cannot get uniform location") };
-        let scale_location = unsafe { gl.get_uniform_location(program, "scale").expect("Cannot get uniform location") };
+    fn draw_slice(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix, seeds: &[&DandelionSeed], fluff: bool) {
+        let mut plain = Vec::new();
+        let mut fancy = Vec::new();
+        for seed in seeds {
+            seed.instance(if seed.fancy { &mut fancy } else { &mut plain });
+        }
         unsafe {
-            gl.uniform_matrix_4_f32_slice(Some(&translation_location), false, &self.translation.to_uniform());
-            gl.uniform_matrix_4_f32_slice(Some(&rotation_location), false, &self.rotation.to_uniform());
-            gl.uniform_matrix_4_f32_slice(Some(&scale_location), false, &self.scale.to_uniform());
+            for (data, buffer, passes) in [
+                (&plain, self.plain_instances, [&self.stem, &self.fluff]),
+                (&fancy, self.fancy_instances, [&self.fancy_stem, &self.fancy_fluff]),
+            ] {
+                let count = data.len() / INSTANCE_FLOATS;
+                if count == 0 {
+                    continue;
+                }
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+                gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytemuck::cast_slice(data), glow::STREAM_DRAW);
+                gl.bind_buffer(glow::ARRAY_BUFFER, None);
+                let [stem, fluff_pass] = passes;
+                stem.draw(gl, view_matrix, projection, count);
+                if fluff {
+                    fluff_pass.draw(gl, view_matrix, projection, count);
+                }
+            }
         }
     }
-}
 
-impl Paintable for DandelionSeed {
-    fn paint(&self, gl: &glow::Context, screen_size: (f32, f32), view_matrix: &AffineMatrix) {
+    pub fn destroy(&self, gl: &glow::Context) {
         unsafe {
-            if !self.fancy {
-                gl.use_program(Some(self.stem_program));
-                let screen_size_location = gl.get_uniform_location(self.stem_program, "screen_size").expect("Cannot get uniform location");
-                gl.uniform_2_f32(Some(&screen_size_location), screen_size.0, screen_size.1);
-                let camera_matrix_location = gl.get_uniform_location(self.stem_program, "view_matrix").expect("Cannot get view_matrix uniform location");
-                gl.uniform_matrix_4_f32_slice(Some(&camera_matrix_location), false, &view_matrix.to_uniform());
-                let color_location = gl.get_uniform_location(self.stem_program, "color").expect("unable to find color location");
-                gl.uniform_4_f32(Some(&color_location), self.color[0], self.color[1], self.color[2], self.color[3]);
-                self.set_transformation_uniforms(gl, self.stem_program);
-
-                gl.bind_vertex_array(Some(self.stem_vao.vao));
-                gl.draw_elements(glow::TRIANGLES, self.stem_vao.num_indices, glow::UNSIGNED_INT, 0);
-                gl.bind_vertex_array(None);
-
-                gl.use_program(Some(self.fluff_program));
-                let screen_size_location = gl.get_uniform_location(self.fluff_program, "screen_size").expect("Cannot get uniform location");
-                gl.uniform_2_f32(Some(&screen_size_location), screen_size.0, screen_size.1);
-                let camera_matrix_location = gl.get_uniform_location(self.fluff_program, "view_matrix").expect("Cannot get view_matrix uniform location");
-                gl.uniform_matrix_4_f32_slice(Some(&camera_matrix_location), false, &view_matrix.to_uniform());
-                let color_location = gl.get_uniform_location(self.fluff_program, "color").expect("unable to find color location");
-                gl.uniform_4_f32(Some(&color_location), self.color[0], self.color[1], self.color[2], self.color[3]);
-                self.set_transformation_uniforms(gl, self.fluff_program);
-
-                gl.bind_vertex_array(Some(self.fluff_vao.vao));
-                gl.draw_elements(glow::TRIANGLES, self.fluff_vao.num_indices, glow::UNSIGNED_INT, 0);
-                gl.bind_vertex_array(None);
-            } else {
-                gl.use_program(Some(self.fancy_program));
-                let screen_size_location = gl.get_uniform_location(self.fancy_program, "screen_size").expect("Cannot get uniform location");
-                gl.uniform_2_f32(Some(&screen_size_location), screen_size.0, screen_size.1);
-                let camera_matrix_location = gl.get_uniform_location(self.fancy_program, "view_matrix").expect("Cannot get view_matrix uniform location");
-                gl.uniform_matrix_4_f32_slice(Some(&camera_matrix_location), false, &view_matrix.to_uniform());
-                let color_location = gl.get_uniform_location(self.fancy_program, "color").expect("unable to find color location");
-                gl.uniform_4_f32(Some(&color_location), self.color[0], self.color[1], self.color[2], self.color[3]);
-                self.set_transformation_uniforms(gl, self.fancy_program);
-
-                gl.bind_vertex_array(Some(self.stem_fancy_vao.vao));
-                gl.draw_elements(glow::TRIANGLES, self.stem_fancy_vao.num_indices, glow::UNSIGNED_INT, 0);
-                gl.bind_vertex_array(None);
-
-                gl.use_program(Some(self.fluff_fancy_program));
-                let screen_size_location = gl.get_uniform_location(self.fluff_fancy_program, "screen_size").expect("Cannot get uniform location");
-                gl.uniform_2_f32(Some(&screen_size_location), screen_size.0, screen_size.1);
-                let camera_matrix_location = gl.get_uniform_location(self.fluff_fancy_program, "view_matrix").expect("Cannot get view_matrix uniform location");
-                gl.uniform_matrix_4_f32_slice(Some(&camera_matrix_location), false, &view_matrix.to_uniform());
-                let color_location = gl.get_uniform_location(self.fluff_fancy_program, "color").expect("unable to find color location");
-                gl.uniform_4_f32(Some(&color_location), self.color[0], self.color[1], self.color[2], self.color[3]);
-                self.set_transformation_uniforms(gl, self.fluff_fancy_program);
-
-                gl.bind_vertex_array(Some(self.fluff_fancy_vao.vao));
-                gl.draw_elements(glow::TRIANGLES, self.fluff_fancy_vao.num_indices, glow::UNSIGNED_INT, 0);
-                gl.bind_vertex_array(None);
+            gl.delete_program(self.stem.program);
+            gl.delete_program(self.fluff.program);
+            gl.delete_program(self.fancy_stem.program);
+            for pass in [&self.stem, &self.fluff, &self.fancy_stem, &self.fancy_fluff] {
+                gl.delete_vertex_array(pass.vao.vao);
             }
-            
+            gl.delete_buffer(self.plain_instances);
+            gl.delete_buffer(self.fancy_instances);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use crate::config::{FilterConfig, FiltersConfig};
+use crate::skeleton::{Joint, Skeleton};
+use crate::vector::distance;
+
+/// One stage of a joint's filter chain. Each frame a stage receives the output of the
+/// previous stage, which is `None` when the tracker had no new sample for the joint (or
+/// an earlier stage rejected it), and returns its own estimate or `None` if it has none.
+pub trait JointFilter: Send {
+    fn update(&mut self, position: Option<[f32; 3]>, time: Instant) -> Option<[f32; 3]>;
+}
+
+impl FilterConfig {
+    fn build(&self) -> Box<dyn JointFilter> {
+        match *self {
+            FilterConfig::OneEuro { min_cutoff, beta, d_cutoff } => Box::new(OneEuroFilter::new(min_cutoff, beta, d_cutoff)),
+            FilterConfig::ConstantVelocity { process_noise, measurement_noise, max_prediction } => {
+                Box::new(ConstantVelocityFilter::new(process_noise, measurement_noise, max_prediction))
+            }
+            FilterConfig::Outlier { max_speed, reset_after } => Box::new(OutlierFilter::new(max_speed, reset_after)),
+            FilterConfig::Hold { timeout } => Box::new(HoldFilter::new(timeout)),
+        }
+    }
+}
+
+struct JointChain {
+    stages: Vec<Box<dyn JointFilter>>,
+    last_sample: Option<Instant>,
+    output: Option<[f32; 3]>,
+    last_known: [f32; 3],
+    /// When the chain last had an estimate.
+    last_estimate: Instant,
+}
+
+/// Runs every joint of every body through its configured filter chain once per frame.
+pub struct FilterPipeline {
+    default: Vec<FilterConfig>,
+    joints: HashMap<Joint, Vec<FilterConfig>>,
+    chains: HashMap<(usize, Joint), JointChain>,
+    forget_after: f32,
+}
+
+impl FilterPipeline {
+    pub fn new(config: &FiltersConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut joints = HashMap::new();
+        for (name, stages) in &config.joints {
+            let joint = Joint::from_name(name).ok_or_else(|| format!("unknown joint {} in filters", name))?;
+            joints.insert(joint, stages.clone());
+        }
+        Ok(Self {
+            default: config.default.clone(),
+            joints,
+            chains: HashMap::new(),
+            forget_after: config.forget_after,
+        })
+    }
+
+    pub fn update(&mut self, skeleton: &Skeleton, now: Instant) {
+        for (body, joint, sample) in skeleton.samples() {
+            let stages = self.joints.get(&joint).unwrap_or(&self.default);
+            let chain = self.chains.entry((body, joint)).or_insert_with(|| JointChain {
+                stages: stages.iter().map(FilterConfig::build).collect(),
+                last_sample: None,
+                output: None,
+                last_known: sample.position,
+                last_estimate: sample.time,
+            });
+
+            let (mut position, time) = if chain.last_sample.map_or(true, |last| sample.time > last) {
+                chain.last_sample = Some(sample.time);
+                (Some(sample.position), sample.time)
+            } else {
+                (None, now)
+            };
+            for stage in chain.stages.iter_mut() {
+                position = stage.update(position, time);
+            }
+            chain.output = position;
+            if let Some(position) = position {
+                chain.last_known = position;
+                chain.last_estimate = now;
+            }
+        }
+    }
+
+    /// The filtered position, or `None` if the chain has no estimate this frame.
+    pub fn position(&self, body: usize, joint: Joint) -> Option<[f32; 3]> {
+        self.chains.get(&(body, joint)).and_then(|chain| chain.output)
+    }
+
+    /// The most recent filtered position the joint ever had.
+    pub fn last_known(&self, body: usize, joint: Joint) -> Option<[f32; 3]> {
+        self.chains.get(&(body, joint)).map(|chain| chain.last_known)
+    }
+
+    /// When the joint last had an estimate, if it has gone without one for longer than
+    /// `forget_after`.
+    pub fn forgotten_since(&self, body: usize, joint: Joint, now: Instant) -> Option<Instant> {
+        let chain = self.chains.get(&(body, joint))?;
+        (chain.output.is_none() && seconds_between(chain.last_estimate, now) > self.forget_after).then_some(chain.last_estimate)
+    }
+
+    /// Whether every chain has a stage that smooths, so that the scene need not smooth the
+    /// joints again.
+    pub fn smooths(&self) -> bool {
+        self.joints.values().chain([&self.default])
+            .all(|stages| stages.iter().any(|stage| matches!(stage, FilterConfig::OneEuro { .. } | FilterConfig::ConstantVelocity { .. })))
+    }
+}
+
+fn seconds_between(earlier: Instant, later: Instant) -> f32 {
+    later.saturating_duration_since(earlier).as_secs_f32()
+}
+
+/// The 1€ filter (Casiez et al.): heavy smoothing while a joint is slow, which backs off
+/// as it speeds up so that fast gestures do not lag.
+pub struct OneEuroFilter {
+    min_cutoff: f32,
+    beta: f32,
+    d_cutoff: f32,
+    state: Option<([f32; 3], [f32; 3], Instant)>,
+}
+
+impl OneEuroFilter {
+    pub fn new(min_cutoff: f32, beta: f32, d_cutoff: f32) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            d_cutoff,
+            state: None,
+        }
+    }
+
+    fn alpha(cutoff: f32, dt: f32) -> f32 {
+        let tau = 1.0 / (2.0 * PI * cutoff);
+        1.0 / (1.0 + tau / dt)
+    }
+}
+
+impl JointFilter for OneEuroFilter {
+    fn update(&mut self, position: Option<[f32; 3]>, time: Instant) -> Option<[f32; 3]> {
+        let position = position?;
+        let (previous, previous_velocity, previous_time) = match self.state {
+            Some(state) => state,
+            None => {
+                self.state = Some((position, [0.0; 3], time));
+                return Some(position);
+            }
+        };
+        let dt = seconds_between(previous_time, time).max(1e-4);
+
+        let d_alpha = Self::alpha(self.d_cutoff, dt);
+        let mut velocity = [0.0; 3];
+        for i in 0..3 {
+            velocity[i] = d_alpha * (position[i] - previous[i]) / dt + (1.0 - d_alpha) * previous_velocity[i];
+        }
+        let speed = distance(velocity, [0.0; 3]);
+
+        let alpha = Self::alpha(self.min_cutoff + self.beta * speed, dt);
+        let mut filtered = [0.0; 3];
+        for i in 0..3 {
+            filtered[i] = alpha * position[i] + (1.0 - alpha) * previous[i];
+        }
+        self.state = Some((filtered, velocity, time));
+        Some(filtered)
+    }
+}
+
+/// A per-axis constant-velocity Kalman filter. It smooths like a low-pass filter while
+/// samples arrive and keeps a joint moving along its last velocity for up to
+/// `max_prediction` seconds when they stop.
+pub struct ConstantVelocityFilter {
+    process_noise: f32,
+    measurement_noise: f32,
+    max_prediction: f32,
+    // per axis position and velocity, with their covariance
+    state: [[f32; 2]; 3],
+    covariance: [[[f32; 2]; 2]; 3],
+    state_time: Option<Instant>,
+    last_measurement: Option<Instant>,
+}
+
+impl ConstantVelocityFilter {
+    pub fn new(process_noise: f32, measurement_noise: f32, max_prediction: f32) -> Self {
+        Self {
+            process_noise,
+            measurement_noise,
+            max_prediction,
+            state: [[0.0; 2]; 3],
+            covariance: [[[0.0; 2]; 2]; 3],
+            state_time: None,
+            last_measurement: None,
+        }
+    }
+
+    fn predict(&mut self, time: Instant) {
+        let dt = match self.state_time {
+            Some(state_time) => seconds_between(state_time, time),
+            None => return,
+        };
+        let q = self.process_noise;
+        for axis in 0..3 {
+            let [p, v] = self.state[axis];
+            self.state[axis] = [p + v * dt, v];
+            let [[p00, p01], [p10, p11]] = self.covariance[axis];
+            self.covariance[axis] = [
+                [
+                    p00 + dt * (p10 + p01) + dt * dt * p11 + q * dt.powi(4) / 4.0,
+                    p01 + dt * p11 + q * dt.powi(3) / 2.0,
+                ],
+                [
+                    p10 + dt * p11 + q * dt.powi(3) / 2.0,
+                    p11 + q * dt * dt,
+                ],
+            ];
+        }
+        self.state_time = Some(time);
+    }
+
+    fn position(&self) -> [f32; 3] {
+        [self.state[0][0], self.state[1][0], self.state[2][0]]
+    }
+}
+
+impl JointFilter for ConstantVelocityFilter {
+    fn update(&mut self, position: Option<[f32; 3]>, time: Instant) -> Option<[f32; 3]> {
+        let measurement = match position {
+            Some(measurement) => measurement,
+            None => {
+                let last_measurement = self.last_measurement?;
+                if seconds_between(last_measurement, time) > self.max_prediction {
+                    return None;
+                }
+                self.predict(time);
+                return Some(self.position());
+            }
+        };
+
+        if self.state_time.is_none() {
+            for (axis, m) in measurement.iter().enumerate() {
+                self.state[axis] = [*m, 0.0];
+                self.covariance[axis] = [[self.measurement_noise, 0.0], [0.0, 1.0]];
+            }
+        } else {
+            self.predict(time);
+            for (axis, m) in measurement.iter().enumerate() {
+                let [[p00, p01], [p10, p11]] = self.covariance[axis];
+                let innovation = m - self.state[axis][0];
+                let s = p00 + self.measurement_noise;
+                let k = [p00 / s, p10 / s];
+                self.state[axis][0] += k[0] * innovation;
+                self.state[axis][1] += k[1] * innovation;
+                self.covariance[axis] = [
+                    [(1.0 - k[0]) * p00, (1.0 - k[0]) * p01],
+                    [p10 - k[1] * p00, p11 - k[1] * p01],
+                ];
+            }
+        }
+        self.state_time = Some(time);
+        self.last_measurement = Some(time);
+        Some(self.position())
+    }
+}
+
+/// Drops samples that would need the joint to move faster than `max_speed` meters per
+/// second, which is what the Kinect produces when it swaps joints between overlapping
+/// dancers. After `reset_after` seconds without an accepted sample the next one is
+/// trusted again.
+pub struct OutlierFilter {
+    max_speed: f32,
+    reset_after: f32,
+    last_accepted: Option<([f32; 3], Instant)>,
+}
+
+impl OutlierFilter {
+    pub fn new(max_speed: f32, reset_after: f32) -> Self {
+        Self {
+            max_speed,
+            reset_after,
+            last_accepted: None,
+        }
+    }
+}
+
+impl JointFilter for OutlierFilter {
+    fn update(&mut self, position: Option<[f32; 3]>, time: Instant) -> Option<[f32; 3]> {
+        let position = position?;
+        if let Some((last, last_time)) = self.last_accepted {
+            let dt = seconds_between(last_time, time);
+            if dt < self.reset_after && distance(position, last) > self.max_speed * dt.max(1.0 / 60.0) {
+                return None;
+            }
+        }
+        self.last_accepted = Some((position, time));
+        Some(position)
+    }
+}
+
+/// Keeps returning the last position for `timeout` seconds after samples stop.
+pub struct HoldFilter {
+    timeout: f32,
+    last: Option<([f32; 3], Instant)>,
+}
+
+impl HoldFilter {
+    pub fn new(timeout: f32) -> Self {
+        Self {
+            timeout,
+            last: None,
+        }
+    }
+}
+
+impl JointFilter for HoldFilter {
+    fn update(&mut self, position: Option<[f32; 3]>, time: Instant) -> Option<[f32; 3]> {
+        if let Some(position) = position {
+            self.last = Some((position, time));
+            return Some(position);
+        }
+        let (last, last_time) = self.last?;
+        if seconds_between(last_time, time) > self.timeout {
+            return None;
+        }
+        Some(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const FRAME: Duration = Duration::from_micros(16_667);
+
+    /// X of what `filter` makes of a joint resting at 0 that jumps to 1 and stays there,
+    /// sampled at 60 Hz, from the jump on.
+    fn step_response(filter: &mut dyn JointFilter, frames: u32) -> Vec<f32> {
+        let start = Instant::now();
+        for frame in 0..10 {
+            filter.update(Some([0.0; 3]), start + FRAME * frame);
+        }
+        (10..10 + frames)
+            .map(|frame| filter.update(Some([1.0, 0.0, 0.0]), start + FRAME * frame).unwrap()[0])
+            .collect()
+    }
+
+    fn assert_settles(response: &[f32]) {
+        assert!(response[0] > 0.0 && response[0] < 1.0, "no smoothing: {}", response[0]);
+        assert!(response.windows(2).all(|pair| pair[1] >= pair[0] - 1e-6), "not monotonic: {:?}", response);
+        assert!(response.iter().all(|&x| x <= 1.0 + 1e-4), "overshoots: {:?}", response);
+        assert!((response.last().unwrap() - 1.0).abs() < 0.01, "does not settle: {:?}", response.last());
+    }
+
+    #[test]
+    fn one_euro_step_response() {
+        assert_settles(&step_response(&mut OneEuroFilter::new(1.0, 0.0, 1.0), 120));
+    }
+
+    #[test]
+    fn one_euro_lags_less_with_speed() {
+        let slow = step_response(&mut OneEuroFilter::new(1.0, 0.0, 1.0), 1)[0];
+        let fast = step_response(&mut OneEuroFilter::new(1.0, 0.5, 1.0), 1)[0];
+        assert!(fast > slow);
+    }
+
+    #[test]
+    fn constant_velocity_step_response() {
+        let response = step_response(&mut ConstantVelocityFilter::new(50.0, 0.0004, 0.3), 120);
+        assert!(response[0] > 0.0 && response[0] < 1.0);
+        assert!((response.last().unwrap() - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn constant_velocity_predicts_only_for_a_while() {
+        let mut filter = ConstantVelocityFilter::new(50.0, 0.0004, 0.3);
+        let start = Instant::now();
+        for frame in 0..60 {
+            filter.update(Some([frame as f32 / 60.0, 0.0, 0.0]), start + FRAME * frame);
+        }
+        let predicted = filter.update(None, start + FRAME * 65).unwrap()[0];
+        assert!(predicted > 59.0 / 60.0, "stopped at {}", predicted);
+        assert!(filter.update(None, start + FRAME * 90).is_none());
+    }
+
+    #[test]
+    fn outlier_drops_jumps_until_reset() {
+        let mut filter = OutlierFilter::new(8.0, 0.5);
+        let start = Instant::now();
+        assert!(filter.update(Some([0.0; 3]), start).is_some());
+        assert!(filter.update(Some([2.0, 0.0, 0.0]), start + FRAME).is_none());
+        assert!(filter.update(Some([0.05, 0.0, 0.0]), start + FRAME * 2).is_some());
+        assert!(filter.update(Some([2.0, 0.0, 0.0]), start + Duration::from_secs(1)).is_some());
+    }
+
+    #[test]
+    fn hold_keeps_the_last_position_until_timeout() {
+        let mut filter = HoldFilter::new(1.0);
+        let start = Instant::now();
+        filter.update(Some([1.0, 2.0, 3.0]), start);
+        assert_eq!(filter.update(None, start + Duration::from_millis(500)), Some([1.0, 2.0, 3.0]));
+        assert_eq!(filter.update(None, start + Duration::from_millis(1500)), None);
+    }
+}
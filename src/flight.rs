@@ -0,0 +1,91 @@
+use crate::affine_matrix::AffineMatrix;
+use crate::config::FlightConfig;
+use crate::vector::{add, cross, dot, normalize, scale, sub};
+use crate::wind::WindField;
+
+/// Seconds per physics step.
+const STEP: f32 = 1.0 / 240.0;
+/// Steps taken at most in one frame, so a long stall does not snowball.
+const MAX_STEPS: u32 = 16;
+const GRAVITY: f32 = 9.81;
+
+/// Pulls a flying seed towards a point, and turns its stem towards `aim`.
+#[derive(Debug, Clone, Copy)]
+pub struct Steering {
+    pub target: [f32; 3],
+    /// Which way the stem should point while steered.
+    pub aim: [f32; 3],
+    /// How firmly it is steered, 0 leaves it to the air.
+    pub weight: f32,
+}
+
+/// A seed flying as a small rigid body: it has mass, its pappus drags it along with the air,
+/// and the air flowing through the pappus rights it, fluff up, and spins it.
+#[derive(Debug, Clone)]
+pub struct Flight {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    /// Unit vector along the stem, from the seed up to the fluff.
+    pub axis: [f32; 3],
+    angular_velocity: [f32; 3],
+    /// Turn of the seed about its stem.
+    pub spin: f32,
+    /// Time not yet simulated, less than a step.
+    leftover: f32,
+}
+
+impl Flight {
+    pub fn new(position: [f32; 3], axis: [f32; 3]) -> Self {
+        Self {
+            position,
+            velocity: [0.0; 3],
+            axis: normalize(axis).unwrap_or([0.0, 1.0, 0.0]),
+            angular_velocity: [0.0; 3],
+            spin: 0.0,
+            leftover: 0.0,
+        }
+    }
+
+    /// Simulates `dt` more seconds in fixed steps. The wind is taken where the seed is at
+    /// the start.
+    pub fn advance(&mut self, config: &FlightConfig, wind: &WindField, steering: Option<Steering>, dt: f32) {
+        let air = wind.sample(self.position);
+        self.leftover += dt;
+        let mut steps = 0;
+        while self.leftover >= STEP {
+            if steps == MAX_STEPS {
+                self.leftover = 0.0;
+                break;
+            }
+            self.step(config, air, steering, STEP);
+            self.leftover -= STEP;
+            steps += 1;
+        }
+    }
+
+    fn step(&mut self, config: &FlightConfig, wind: [f32; 3], steering: Option<Steering>, dt: f32) {
+        let relative = sub(wind, self.velocity);
+        let mut acceleration = scale(relative, config.drag / config.mass.max(1e-3));
+        acceleration[1] -= GRAVITY;
+        let mut desired = normalize(relative).unwrap_or([0.0, 1.0, 0.0]);
+        if let Some(steering) = steering {
+            let weight = steering.weight.clamp(0.0, 1.0);
+            acceleration = add(acceleration, scale(sub(steering.target, self.position), weight * config.steering));
+            if let Some(aim) = normalize(steering.aim) {
+                desired = normalize(add(scale(desired, 1.0 - weight), scale(aim, weight))).unwrap_or(aim);
+            }
+        }
+        // semi-implicit Euler, so the stiff drag stays stable
+        self.velocity = add(self.velocity, scale(acceleration, dt));
+        self.position = add(self.position, scale(self.velocity, dt));
+
+        let torque = sub(scale(cross(self.axis, desired), config.righting), scale(self.angular_velocity, config.angular_damping));
+        self.angular_velocity = add(self.angular_velocity, scale(torque, dt));
+        self.axis = normalize(add(self.axis, scale(cross(self.angular_velocity, self.axis), dt))).unwrap_or(self.axis);
+        self.spin += config.spin * dot(relative, self.axis) * dt;
+    }
+
+    pub fn rotation(&self) -> AffineMatrix {
+        AffineMatrix::pointing(self.axis, self.spin)
+    }
+}
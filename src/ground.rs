@@ -1,8 +1,6 @@
 use eframe::egui::Color32;
 use eframe::{egui::accesskit::Affine, egui_glow, glow::HasContext};
 use egui_glow::glow;
-use core::time;
-use std::time::Instant;
 
 use crate::color::Color;
 use crate::{obj::JoinedOBJ, scene::Paintable};
@@ -11,7 +9,6 @@ use crate::affine_matrix::AffineMatrix;
 pub struct Ground {
     program: glow::Program,
     obj: JoinedOBJ,
-    time: Instant,
     pub translation: AffineMatrix,
     pub rotation: AffineMatrix,
     pub scale: AffineMatrix,
@@ -29,7 +26,6 @@ impl Ground {
         Self {
             program,
             obj,
-            time: Instant::now(),
             translation,
             rotation,
             scale,
@@ -66,6 +62,13 @@ impl Ground {
         program
     }
 
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.obj.vao);
+        }
+    }
+
     fn set_transformation_uniforms(&self, gl: &glow::Context) {
         let translation_location = unsafe { gl.get_uniform_location(self.program, "translation").expect("Cannot get uniform location") };
         let rotation_location = unsafe { gl.get_uniform_location(self.program, "rotation").expect("Cannot get uniform location") };
@@ -79,11 +82,11 @@ impl Ground {
 }
 
 impl Paintable for Ground {
-    fn paint(&self, gl: &glow::Context, screen_size: (f32, f32), view_matrix: &AffineMatrix) {
+    fn paint(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix) {
         unsafe {
             gl.use_program(Some(self.program));
-            let screen_size_location = gl.get_uniform_location(self.program, "screen_size").expect("Cannot get uniform location");
-            gl.uniform_2_f32(Some(&screen_size_location), screen_size.0, screen_size.1);
+            let projection_location = gl.get_uniform_location(self.program, "projection").expect("Cannot get projection uniform location");
+            gl.uniform_matrix_4_f32_slice(Some(&projection_location), false, projection.to_uniform());
             let camera_matrix_location = gl.get_uniform_location(self.program, "view_matrix").expect("Cannot get view_matrix uniform location");
             gl.uniform_matrix_4_f32_slice(Some(&camera_matrix_location), false, &view_matrix.to_uniform());
             let color_location = gl.get_uniform_location(self.program, "color").expect("unable to find color location");
@@ -1,100 +1,315 @@
-use rosc::{OscPacket, OscType};
-use std::env;
+use rosc::{OscMessage, OscPacket, OscTime, OscType};
+use std::io::ErrorKind;
 use std::net::{SocketAddrV4, UdpSocket};
-use std::str::FromStr;
-
-use crate::{BODY1_BASE_SPINE, BODY2_BASE_SPINE, BODY1_HEAD, BODY2_HEAD};
-
-const ADDR: &str = "127.0.0.1:9000";
-
-pub fn spawn_osc_handler() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = SocketAddrV4::from_str(ADDR)?;
-    let sock = UdpSocket::bind(addr).unwrap();
-    println!("Listening to {}", addr);
-
-    let mut buf = [0u8; rosc::decoder::MTU];
-
-    std::thread::spawn(move || {
-        loop {
-            match sock.recv_from(&mut buf) {
-                Ok((size, addr)) => {
-                    let (_, packet) = match rosc::decoder::decode_udp(&buf[..size]) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            println!("{}", e);
-                            continue;
-                        }
-                    };
-                    handle_packet(packet);
-                }
-                Err(e) => {
-                    println!("Error receiving from socket: {}", e);
-                    continue;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::body_source::{BodySink, BodySource};
+use crate::config::{JointMapping, OscConfig};
+use crate::cues::{Parameter, ShowCommand, Trigger};
+use crate::recording::Recorder;
+use crate::skeleton::{Joint, JointUpdate};
+use crate::CALIBRATION;
+
+/// The OSC timetag meaning "apply immediately".
+const IMMEDIATE: OscTime = OscTime { seconds: 0, fractional: 1 };
+/// Seconds between the OSC (NTP) epoch and the unix epoch.
+const UNIX_OFFSET: u32 = 2_208_988_800;
+
+/// Addresses under this prefix control the show instead of carrying joints.
+const CONTROL_PREFIX: &str = "/show/";
+
+/// Joint updates that must reach the skeleton together, at `time`, and show commands
+/// scheduled for the same moment.
+struct Frame {
+    time: Instant,
+    updates: Vec<JointUpdate>,
+    commands: Vec<ShowCommand>,
+}
+
+enum Capture {
+    Body,
+    Joint,
+}
+
+struct PatternSegment {
+    prefix: String,
+    capture: Option<Capture>,
+    suffix: String,
+}
+
+/// An OSC address pattern from the config, where a path segment may capture the body
+/// number with `{body}` or the joint name with `{joint}`, e.g. `/joint/body{body}/{joint}`.
+pub struct JointPattern {
+    segments: Vec<PatternSegment>,
+    body: Option<usize>,
+    joint: Option<Joint>,
+}
+
+impl JointPattern {
+    pub fn new(mapping: &JointMapping) -> Result<Self, Box<dyn std::error::Error>> {
+        let pattern = &mapping.pattern;
+        let joint = match &mapping.joint {
+            Some(name) => Some(Joint::from_name(name).ok_or_else(|| format!("unknown joint {} for pattern {}", name, pattern))?),
+            None => None,
+        };
+        let segments = pattern
+            .strip_prefix('/')
+            .ok_or_else(|| format!("pattern {} must start with /", pattern))?
+            .split('/')
+            .map(|segment| {
+                for (placeholder, capture) in [("{body}", Capture::Body), ("{joint}", Capture::Joint)] {
+                    if let Some((prefix, suffix)) = segment.split_once(placeholder) {
+                        return PatternSegment { prefix: prefix.to_string(), capture: Some(capture), suffix: suffix.to_string() };
+                    }
                 }
+                PatternSegment { prefix: segment.to_string(), capture: None, suffix: String::new() }
+            })
+            .collect::<Vec<_>>();
+
+        let body_captures = segments.iter().filter(|s| matches!(s.capture, Some(Capture::Body))).count();
+        let joint_captures = segments.iter().filter(|s| matches!(s.capture, Some(Capture::Joint))).count();
+        match (body_captures, mapping.body) {
+            (0, None) => return Err(format!("pattern {} needs a {{body}} or a body", pattern).into()),
+            (0, Some(_)) | (1, None) => {}
+            _ => return Err(format!("pattern {} must set its body exactly once", pattern).into()),
+        }
+        match (joint_captures, joint) {
+            (0, None) => return Err(format!("pattern {} needs a {{joint}} or a joint", pattern).into()),
+            (0, Some(_)) | (1, None) => {}
+            _ => return Err(format!("pattern {} must set its joint exactly once", pattern).into()),
+        }
+
+        Ok(Self {
+            segments,
+            body: mapping.body,
+            joint,
+        })
+    }
+
+    fn matches(&self, addr: &str) -> Option<(usize, Joint)> {
+        let parts = addr.strip_prefix('/')?.split('/').collect::<Vec<_>>();
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        let mut body = self.body;
+        let mut joint = self.joint;
+        for (part, segment) in parts.iter().zip(&self.segments) {
+            let middle = part.strip_prefix(segment.prefix.as_str())?.strip_suffix(segment.suffix.as_str())?;
+            match segment.capture {
+                Some(Capture::Body) => body = Some(middle.parse().ok()?),
+                Some(Capture::Joint) => joint = Some(Joint::from_name(middle)?),
+                None if !middle.is_empty() => return None,
+                None => {}
             }
         }
-    });
-    Ok(())
+        Some((body?, joint?))
+    }
 }
 
-fn handle_packet(packet: OscPacket) {
-    match packet {
-        OscPacket::Message(msg) => {
-            if msg.addr == "/body1/spine_mid" {
-                let mut pos = match handle_3d_position_osc_msg(&msg.args) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        println!("{}", e);
-                        return;
-                    }
-                };
-                pos[0] = -pos[0];
-                pos[2] = -pos[2];
-                if pos != [0.0, 0.0, 0.0] {
-                    *BODY1_BASE_SPINE.lock() = pos;
-                }
-            } else if msg.addr == "/body2/spine_mid" {
-                let mut pos = match handle_3d_position_osc_msg(&msg.args) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        println!("{}", e);
-                        return;
-                    }
-                };
-                pos[0] = -pos[0];
-                pos[2] = -pos[2];
-                if pos != [0.0, 0.0, 0.0] {
-                    *BODY2_BASE_SPINE.lock() = pos;
+/// Listens for joints on the configured address, writing everything it receives to
+/// `recorder` if there is one, and passes show control messages on to `commands`.
+pub struct OscSource {
+    sock: UdpSocket,
+    patterns: Vec<JointPattern>,
+    recorder: Option<Recorder>,
+    commands: Option<Sender<ShowCommand>>,
+}
+
+impl OscSource {
+    pub fn new(config: OscConfig, recorder: Option<Recorder>, commands: Option<Sender<ShowCommand>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let patterns = config.joints.iter().map(JointPattern::new).collect::<Result<Vec<_>, _>>()?;
+        let addr = SocketAddrV4::new(config.address, config.port);
+        let sock = UdpSocket::bind(addr)
+            .map_err(|e| format!("Cannot listen for OSC on {}: {}", addr, e))?;
+        println!("Listening to {}", addr);
+        Ok(Self {
+            sock,
+            patterns,
+            recorder,
+            commands,
+        })
+    }
+}
+
+impl BodySource for OscSource {
+    fn start(self: Box<Self>, sink: BodySink) -> Result<(), Box<dyn std::error::Error>> {
+        let OscSource { sock, patterns, mut recorder, commands } = *self;
+        let mut buf = [0u8; rosc::decoder::MTU];
+
+        std::thread::spawn(move || {
+            // bundles timetagged in the future wait here until they are due
+            let mut pending: Vec<Frame> = Vec::new();
+            loop {
+                apply_due_frames(&mut pending, &sink, recorder.as_mut(), commands.as_ref());
+                let timeout = pending.first().map(|frame| frame.time.saturating_duration_since(Instant::now()).max(Duration::from_millis(1)));
+                if let Err(e) = sock.set_read_timeout(timeout) {
+                    println!("Error setting socket timeout: {}", e);
                 }
-            } else if msg.addr == "/body1/head" {
-                let mut pos = match handle_3d_position_osc_msg(&msg.args) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        println!("{}", e);
-                        return;
+
+                match sock.recv_from(&mut buf) {
+                    Ok((size, _)) => {
+                        let (_, packet) = match rosc::decoder::decode_udp(&buf[..size]) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
+                        handle_packet(packet, &patterns, commands.is_some(), Instant::now(), &mut pending);
+                        pending.sort_by_key(|frame| frame.time);
                     }
-                };
-                pos[0] = -pos[0];
-                pos[2] = -pos[2];
-                *BODY1_HEAD.lock() = pos;
-            } else if msg.addr == "/body2/head" {
-                let mut pos = match handle_3d_position_osc_msg(&msg.args) {
-                    Ok(v) => v,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
                     Err(e) => {
-                        println!("{}", e);
-                        return;
+                        println!("Error receiving from socket: {}", e);
+                        continue;
                     }
-                };
-                pos[0] = -pos[0];
-                pos[2] = -pos[2];
-                *BODY2_HEAD.lock() = pos;
-            } else {
-                println!("address not recognized: {:?}", msg);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+fn apply_due_frames(pending: &mut Vec<Frame>, sink: &BodySink, mut recorder: Option<&mut Recorder>, commands: Option<&Sender<ShowCommand>>) {
+    let now = Instant::now();
+    let due = pending.iter().take_while(|frame| frame.time <= now).count();
+    for frame in pending.drain(..due) {
+        sink.apply(&frame.updates, frame.time);
+        if let Some(recorder) = recorder.as_mut() {
+            if let Err(e) = recorder.record(frame.time, &frame.updates) {
+                println!("Error recording tracker frame: {}", e);
+            }
+        }
+        if let Some(commands) = commands {
+            for command in frame.commands {
+                // the receiving end only goes away with the app
+                let _ = commands.send(command);
+            }
+        }
+    }
+}
+
+/// Collects the joint updates in `packet` into `frames`. Bundles are unpacked
+/// recursively, and every message in a bundle shares that bundle's frame so that a
+/// whole skeleton arrives in the same render.
+fn handle_packet(packet: OscPacket, patterns: &[JointPattern], control: bool, time: Instant, frames: &mut Vec<Frame>) {
+    match packet {
+        OscPacket::Message(msg) => {
+            if control && msg.addr.starts_with(CONTROL_PREFIX) {
+                if let Some(command) = handle_control_msg(&msg) {
+                    frame_at(frames, time).commands.push(command);
+                }
+            } else if let Some(update) = handle_joint_msg(&msg, patterns) {
+                frame_at(frames, time).updates.push(update);
             }
         }
         OscPacket::Bundle(bundle) => {
-            println!("OSC Bundle: {:?}", bundle);
+            // a nested bundle may not be scheduled before the bundle that contains it
+            let time = timetag_to_instant(bundle.timetag).max(time);
+            for packet in bundle.content {
+                handle_packet(packet, patterns, control, time, frames);
+            }
+        }
+    }
+}
+
+fn frame_at(frames: &mut Vec<Frame>, time: Instant) -> &mut Frame {
+    let index = match frames.iter().position(|frame| frame.time == time) {
+        Some(i) => i,
+        None => {
+            frames.push(Frame { time, updates: Vec::new(), commands: Vec::new() });
+            frames.len() - 1
         }
+    };
+    &mut frames[index]
+}
+
+/// Converts a timetag to the matching `Instant`, which is in the past for late bundles.
+fn timetag_to_instant(timetag: OscTime) -> Instant {
+    let now = Instant::now();
+    if timetag == IMMEDIATE || timetag.seconds < UNIX_OFFSET {
+        return now;
+    }
+    let target = SystemTime::from(timetag);
+    match target.duration_since(SystemTime::now()) {
+        Ok(ahead) => now + ahead,
+        Err(e) => now.checked_sub(e.duration()).unwrap_or(now),
+    }
+}
+
+fn handle_joint_msg(msg: &OscMessage, patterns: &[JointPattern]) -> Option<JointUpdate> {
+    let (body, joint) = match patterns.iter().find_map(|pattern| pattern.matches(&msg.addr)) {
+        Some(v) => v,
+        None => {
+            println!("address not recognized: {:?}", msg);
+            return None;
+        }
+    };
+    let pos = match handle_3d_position_osc_msg(&msg.args) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", e);
+            return None;
+        }
+    };
+    // the Kinect reports untracked joints at the origin
+    if pos == [0.0, 0.0, 0.0] {
+        return None;
+    }
+    let position = CALIBRATION.lock().transform_point(pos);
+    Some(JointUpdate { body, joint, position })
+}
+
+/// Parses `/show/go`, `/show/back`, `/show/start`, `/show/stop`, `/show/release`,
+/// `/show/regrow`, `/show/cue <number or name>` and `/show/<level> <value> [fade seconds]`
+/// for the levels in `cues::Parameter`.
+fn handle_control_msg(msg: &OscMessage) -> Option<ShowCommand> {
+    let command = &msg.addr[CONTROL_PREFIX.len()..];
+    let command = match (command, msg.args.as_slice()) {
+        // buttons in Max and QLab often send a value along, which does not matter here
+        ("go", _) => ShowCommand::Go,
+        ("back", _) => ShowCommand::Back,
+        ("start", _) => ShowCommand::Start,
+        ("stop", _) => ShowCommand::Stop,
+        ("release", _) => ShowCommand::Trigger(Trigger::Release),
+        ("regrow", _) => ShowCommand::Trigger(Trigger::Regrow),
+        ("cue", [OscType::String(name)]) => ShowCommand::CueNamed(name.clone()),
+        ("cue", [number]) => match osc_number(number) {
+            Some(number) if number >= 1.0 && number.fract() == 0.0 => ShowCommand::Cue(number as usize),
+            _ => {
+                println!("{}: expected a cue number or name, got {:?}", msg.addr, msg.args);
+                return None;
+            }
+        },
+        (name, args) => {
+            let parameter = match Parameter::from_name(name) {
+                Some(parameter) => parameter,
+                None => {
+                    println!("address not recognized: {:?}", msg);
+                    return None;
+                }
+            };
+            match args.iter().map(osc_number).collect::<Option<Vec<_>>>().as_deref() {
+                Some([value]) => ShowCommand::Set { parameter, value: *value, fade: 0.0 },
+                Some([value, fade]) => ShowCommand::Set { parameter, value: *value, fade: *fade },
+                _ => {
+                    println!("{}: expected a value and an optional fade time, got {:?}", msg.addr, msg.args);
+                    return None;
+                }
+            }
+        }
+    };
+    Some(command)
+}
+
+fn osc_number(arg: &OscType) -> Option<f32> {
+    match *arg {
+        OscType::Float(f) => Some(f),
+        OscType::Double(d) => Some(d as f32),
+        OscType::Int(i) => Some(i as f32),
+        OscType::Long(l) => Some(l as f32),
+        _ => None,
     }
 }
 
@@ -1,10 +1,22 @@
-use dancer_mock::spawn_dancer_mock;
 use eframe::{egui::{self, Margin, viewport::ViewportCommand}, egui_glow, epaint::Color32, App, CreationContext, Frame};
 use dandelion::DandelionSeed;
 use egui::mutex::Mutex;
 use std::{num, sync::Arc};
 use scene::{Scene, Paintable};
-use kinect_tracker::spawn_osc_handler;
+use skeleton::Skeleton;
+use config::{CalibrationConfig, Config};
+use affine_matrix::AffineMatrix;
+use calibration::CalibrationSession;
+use filter::FilterPipeline;
+use body_source::SourceHandles;
+use cues::{CuePlayer, Parameter};
+use osc_output::OscOutput;
+use audio::AudioSignals;
+use projectors::Projectors;
+use config::WarpConfig;
+use warp::Warp;
+use clock::Clock;
+use std::time::Instant;
 
 mod dandelion;
 mod obj;
@@ -14,58 +26,205 @@ mod affine_matrix;
 mod kinect_tracker;
 mod dancer_mock;
 mod color;
+mod skeleton;
+mod config;
+mod calibration;
+mod filter;
+mod recording;
+mod body_source;
+mod puppet;
+mod choreography;
+mod transition;
+mod cues;
+mod osc_output;
+mod audio;
+mod assignment;
+mod seed_head;
+mod wind;
+mod flight;
+mod camera;
+mod clock;
+mod vector;
+mod projectors;
+mod warp;
+mod post;
+mod render;
 //mod dandelion_joined;
 
 lazy_static::lazy_static! {
-    pub static ref BODY1_BASE_SPINE: Mutex<[f32; 3]> = Mutex::new([0.0, 0.0, 0.0]);
-    pub static ref BODY2_BASE_SPINE: Mutex<[f32; 3]> = Mutex::new([0.0, 0.0, 0.0]);
-    pub static ref BODY1_HEAD: Mutex<[f32; 3]> = Mutex::new([0.0, 0.0, 0.0]);
-    pub static ref BODY2_HEAD: Mutex<[f32; 3]> = Mutex::new([0.0, 0.0, 0.0]);
+    pub static ref SKELETON: Mutex<Skeleton> = Mutex::new(Skeleton::new());
+    /// Sensor-to-stage transform applied to every incoming joint.
+    pub static ref CALIBRATION: Mutex<AffineMatrix> = Mutex::new(calibration::default_transform());
+    /// The latest analysis of the score, see `audio::spawn_analysis`.
+    pub static ref AUDIO: Mutex<AudioSignals> = Mutex::new(AudioSignals::default());
 }
 
-const AFFECTION_STEP_SIZE: f32 = 0.001;
+/// Seconds W takes to raise affection from 0 to 1, and S to drop it back to 0.
+const AFFECTION_RISE_TIME: f32 = 16.7;
+const AFFECTION_FALL_TIME: f32 = 1.7;
+/// Meters per second the arrow keys move the camera.
+const CAMERA_SPEED: f32 = 0.6;
+const DIGIT_KEYS: [(egui::Key, char); 10] = [
+    (egui::Key::Num0, '0'), (egui::Key::Num1, '1'), (egui::Key::Num2, '2'), (egui::Key::Num3, '3'), (egui::Key::Num4, '4'),
+    (egui::Key::Num5, '5'), (egui::Key::Num6, '6'), (egui::Key::Num7, '7'), (egui::Key::Num8, '8'), (egui::Key::Num9, '9'),
+];
 
 #[derive(Debug, Clone, Copy)]
 struct DandelionState {
-    pub started: bool,
     pub brightness: f32,
     pub affection: f32,
-    pub scene_3: bool,
     pub dancing_brightness: f32,
-    pub scene_5: bool,
     pub drift_strength: f32,
+    pub bloom: f32,
+    pub grain: f32,
+    pub vignette: f32,
 }
 
 struct DandelionApp {
     scene: Arc<Mutex<Scene>>,
+    projectors: Arc<Mutex<Projectors>>,
     fullscreen: bool,
-    state: DandelionState,
+    cues: CuePlayer,
+    cue_message: String,
+    last_frame: Instant,
+    clock: Clock,
+    calibration_config: CalibrationConfig,
+    calibration: Option<CalibrationSession>,
+    calibration_message: String,
+    warp_config: WarpConfig,
+    /// The mouse drags the warp handles.
+    warp_editing: bool,
+    warp_message: String,
+    sources: SourceHandles,
 }
 
 impl DandelionApp {
-    fn new(cc: &CreationContext) -> Self {
+    fn new(cc: &CreationContext, config: Config, filters: FilterPipeline, sources: SourceHandles, cues: CuePlayer, output: Option<OscOutput>, warps: Vec<Warp>) -> Self {
         let gl = cc.gl.as_ref()
             .expect("No OpenGL context");
-        let state = DandelionState {
-            started: false,
-            brightness: 0.0,
-            affection: 0.0,
-            scene_3: false,
-            dancing_brightness: 0.0,
-            scene_5: false,
-            drift_strength: 0.0,
-
-        };
+        let clock = Clock::realtime();
         Self {
-            scene: Arc::new(Mutex::new(Scene::new(gl))),
+            scene: Arc::new(Mutex::new(Scene::new(gl, &config, filters, output, &clock))),
+            projectors: Arc::new(Mutex::new(Projectors::new(gl, &config, &warps))),
             fullscreen: false,
-            state,
+            cues,
+            cue_message: String::new(),
+            last_frame: Instant::now(),
+            clock,
+            calibration_config: config.calibration,
+            calibration: None,
+            calibration_message: String::new(),
+            warp_config: config.warp,
+            warp_editing: false,
+            warp_message: String::new(),
+            sources,
+        }
+    }
+
+    /// C starts or abandons calibration, Enter captures the current stage point and
+    /// Backspace recaptures the previous one.
+    fn handle_calibration(&mut self, ui: &mut egui::Ui) {
+        if ui.input(|i| i.key_pressed(egui::Key::C)) {
+            if self.calibration.take().is_some() {
+                self.calibration_message.clear();
+            } else {
+                match CalibrationSession::new(&self.calibration_config) {
+                    Ok(session) => self.calibration = Some(session),
+                    Err(e) => self.calibration_message = format!("Calibration: {}", e),
+                }
+            }
+        }
+        let session = match self.calibration.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+
+        if ui.input(|i| i.key_pressed(egui::Key::Backspace)) {
+            session.undo();
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            if let Err(e) = session.capture(&SKELETON.lock()) {
+                self.calibration_message = format!("Calibration: {}", e);
+                return;
+            }
+        }
+
+        if session.is_complete() {
+            let current = *CALIBRATION.lock();
+            self.calibration_message = match session.solve(&current) {
+                Ok((transform, rms)) => {
+                    *CALIBRATION.lock() = transform;
+                    match calibration::save(&self.calibration_config.file, &transform) {
+                        Ok(()) => format!("Calibration saved to {} (error {:.3} m)", self.calibration_config.file.display(), rms),
+                        Err(e) => format!("Calibration applied but not saved (error {:.3} m): {}", rms, e),
+                    }
+                }
+                Err(e) => format!("Calibration failed: {}", e),
+            };
+            self.calibration = None;
+        } else if let Some((number, target)) = session.current_target() {
+            self.calibration_message = format!(
+                "Calibration: stand on point {} of {} ({:.2}, {:.2}, {:.2}) and press Enter",
+                number, session.num_targets(), target[0], target[1], target[2],
+            );
+        }
+    }
+
+    /// P starts editing the warps with the mouse, and saves them when pressed again.
+    fn handle_warp(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        if ui.input(|i| i.key_pressed(egui::Key::P)) {
+            self.warp_editing = !self.warp_editing;
+            self.warp_message = if self.warp_editing {
+                "Warp: drag the corners and grid points, R resets the output under the mouse, P saves".to_string()
+            } else {
+                let warps = self.projectors.lock().warps();
+                match warp::save(&self.warp_config.file, &warps) {
+                    Ok(()) => String::new(),
+                    Err(e) => format!("Warp: {}", e),
+                }
+            };
+        }
+        if self.warp_editing {
+            self.projectors.lock().edit_warps(ui, rect);
+        }
+    }
+
+    /// Space is GO, B goes back a cue, and typing a cue number then G jumps to it. Commands
+    /// also arrive over OSC.
+    fn handle_cues(&mut self, ui: &mut egui::Ui, now: Instant) {
+        if ui.input(|i| i.key_pressed(egui::Key::Space)) {
+            self.cues.go(now);
+            self.cue_message.clear();
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::B)) {
+            self.cues.back(now);
+            self.cue_message.clear();
+        }
+        for (key, digit) in DIGIT_KEYS {
+            if ui.input(|i| i.key_pressed(key)) {
+                self.cues.type_jump(digit);
+            }
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::G)) {
+            self.cue_message = match self.cues.confirm_jump(now) {
+                Ok(()) => String::new(),
+                Err(e) => e,
+            };
+        }
+        if let Some(show) = &self.sources.show {
+            for command in show.try_iter() {
+                self.cue_message = match self.cues.handle(command, now) {
+                    Ok(()) => String::new(),
+                    Err(e) => format!("OSC: {}", e),
+                };
+            }
         }
     }
 
     fn draw_scene(&mut self, ui: &mut egui::Ui) {
         let rect = ui.available_rect_before_wrap();
         let scene = self.scene.clone();
+        let projectors = self.projectors.clone();
         let mut motion_vector = [0; 2];
         if ui.input(|i| i.key_down(egui::Key::ArrowUp)) {
             motion_vector[0] += 1;
@@ -80,69 +239,82 @@ impl DandelionApp {
             motion_vector[1] -= 1;
         }
 
-        if ui.input(|i| i.key_pressed(egui::Key::Num3)) {
-            self.state.scene_3 = !self.state.scene_3;
-        }
-        if ui.input(|i| i.key_pressed(egui::Key::Num5)) {
-            self.state.scene_5 = !self.state.scene_5;
-        }
-
-        if ui.input(|i| i.key_pressed(egui::Key::Space)) {
-            self.state.started = !self.state.started;
-        }
+        let now = self.clock.now();
+        let dt = now.saturating_duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.handle_cues(ui, now);
 
-        if ui.input(|i| i.key_down(egui::Key::W)) {
-            if self.state.affection < 1.0 {
-                self.state.affection += AFFECTION_STEP_SIZE;
+        if let Some(replay) = &self.sources.replay {
+            if ui.input(|i| i.key_pressed(egui::Key::Period)) {
+                replay.step();
             }
         }
-        if ui.input(|i| i.key_down(egui::Key::S)) {
-            if self.state.affection > 0.0 {
-                self.state.affection -= AFFECTION_STEP_SIZE * 10.0;
-            }
-        }
-
-        let dancing_transition_speed = 0.01;
-        if self.state.started && self.state.scene_3 && self.state.dancing_brightness < 1.0 {
-            self.state.dancing_brightness += dancing_transition_speed;
-            if self.state.brightness > 0.0 {
-                self.state.brightness -= dancing_transition_speed;
-            }
-        }
-        if self.state.started && !self.state.scene_3 && self.state.dancing_brightness > 0.0 {
-            self.state.dancing_brightness = 0_f32.max(self.state.dancing_brightness - dancing_transition_speed);
-            if self.state.brightness < 1.0 {
-                self.state.brightness += dancing_transition_speed;
-            }
-        }
-
-        if self.state.started && self.state.brightness < 1.0 && self.state.dancing_brightness == 0.0 {
-            self.state.brightness += 0.01;
-        }
-        if !self.state.started && self.state.brightness > 0.0 && self.state.dancing_brightness == 0.0 {
-            self.state.brightness -= 0.01;
+        if let Some(puppet) = self.sources.puppet.as_ref().filter(|_| !self.warp_editing) {
+            puppet.handle_input(ui);
         }
 
-        if self.state.started && self.state.scene_5 && self.state.drift_strength < 1.0 {
-            self.state.drift_strength += 0.0001;
+        if ui.input(|i| i.key_down(egui::Key::W)) {
+            self.cues.nudge(Parameter::Affection, dt / AFFECTION_RISE_TIME, now);
         }
-        if self.state.started && !self.state.scene_5 && self.state.drift_strength > 0.0 {
-            self.state.drift_strength -= 0.0001;
+        if ui.input(|i| i.key_down(egui::Key::S)) {
+            self.cues.nudge(Parameter::Affection, -dt / AFFECTION_FALL_TIME, now);
         }
-        //println!("brightness: {}", self.state.brightness);
-        let state = self.state;
+        let state = self.cues.state(now);
+        let triggers = self.cues.take_triggers();
 
         let callback = egui::PaintCallback {
             rect,
-            callback: Arc::new(egui_glow::CallbackFn::new(move |_info, painter| {
+            callback: Arc::new(egui_glow::CallbackFn::new(move |info, painter| {
                 let mut scene = scene.lock();
-                scene.update(state);
-                scene.camera_pos[2] += motion_vector[0] as f32 * -0.01;
-                scene.camera_pos[0] += motion_vector[1] as f32 * 0.01;
-                scene.paint(painter.gl(), (rect.width(), rect.height()), state);
+                for &trigger in &triggers {
+                    scene.trigger(trigger);
+                }
+                scene.update(state, now);
+                scene.camera.move_by(motion_vector[0] as f32 * CAMERA_SPEED * dt, motion_vector[1] as f32 * CAMERA_SPEED * dt);
+                let viewport = info.viewport_in_pixels();
+                let window = [viewport.left_px, viewport.from_bottom_px, viewport.width_px, viewport.height_px];
+                projectors.lock().paint(painter.gl(), painter.intermediate_fbo(), window, &scene, state);
             }))
         };
         ui.painter().add(callback);
+
+        self.handle_warp(ui, rect);
+        self.handle_calibration(ui);
+        let message = [&self.calibration_message, &self.warp_message]
+            .into_iter()
+            .filter(|message| !message.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !message.is_empty() {
+            ui.painter().text(
+                rect.left_top() + egui::vec2(16.0, 16.0),
+                egui::Align2::LEFT_TOP,
+                message,
+                egui::FontId::proportional(18.0),
+                Color32::WHITE,
+            );
+        }
+        let mut cue_status = self.cues.status(now);
+        if !self.cue_message.is_empty() {
+            cue_status += &format!("\n{}", self.cue_message);
+        }
+        ui.painter().text(
+            rect.right_top() + egui::vec2(-16.0, 16.0),
+            egui::Align2::RIGHT_TOP,
+            cue_status,
+            egui::FontId::proportional(18.0),
+            Color32::WHITE,
+        );
+        if let Some(puppet) = &self.sources.puppet {
+            ui.painter().text(
+                rect.left_bottom() + egui::vec2(16.0, -16.0),
+                egui::Align2::LEFT_BOTTOM,
+                puppet.status(),
+                egui::FontId::proportional(18.0),
+                Color32::WHITE,
+            );
+        }
     }
 }
 
@@ -173,14 +345,97 @@ impl App for DandelionApp {
         });
         ctx.request_repaint_after(std::time::Duration::from_secs_f64(1.0 / 60.0));
     }
+
+    fn on_exit(&mut self, gl: Option<&egui_glow::glow::Context>) {
+        if let Some(gl) = gl {
+            self.scene.lock().destroy(gl);
+            self.projectors.lock().destroy(gl);
+        }
+    }
 }
 
 fn main() {
-    spawn_osc_handler().unwrap();
-    spawn_dancer_mock().unwrap();
+    let config = match Config::from_args(std::env::args()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if config.calibration.file.exists() {
+        match calibration::load(&config.calibration.file) {
+            Ok(transform) => *CALIBRATION.lock() = transform,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let warps = if config.warp.file.exists() {
+        match warp::load(&config.warp.file) {
+            Ok(warps) => warps,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let filters = match FilterPipeline::new(&config.filters) {
+        Ok(filters) => filters,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let cues = match &config.cues.file {
+        Some(path) => match cues::load(path) {
+            Ok(cues) => cues,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => cues::default_cues(),
+    };
+    if config.render.output.is_some() {
+        if let Err(e) = render::render(&config, filters, cues, &warps) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let output = match OscOutput::new(&config.osc_out) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = audio::spawn_analysis(&config.audio) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let sources = match body_source::start_sources(&config) {
+        Ok(sources) => sources,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
     let mut native_options = eframe::NativeOptions::default();
     native_options.multisampling = 8;
-    eframe::run_native("Dandelions", native_options, Box::new(|cc| Box::new(DandelionApp::new(cc))))
+    // to span several projectors, open borderless across all of them
+    let mut viewport = egui::ViewportBuilder::default().with_decorations(!config.window.borderless);
+    if let Some(position) = config.window.position {
+        viewport = viewport.with_position(position);
+    }
+    if let Some(size) = config.window.size {
+        viewport = viewport.with_inner_size(size);
+    }
+    native_options.viewport = viewport;
+    eframe::run_native("Dandelions", native_options, Box::new(|cc| Box::new(DandelionApp::new(cc, config, filters, sources, CuePlayer::new(cues), output, warps))))
         .unwrap();
 }
 
@@ -0,0 +1,112 @@
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::config::OscOutputConfig;
+use crate::DandelionState;
+
+const IMMEDIATE: OscTime = OscTime { seconds: 0, fractional: 1 };
+
+/// What the scene publishes each time the output is due.
+pub struct SceneReport {
+    /// Position, stem direction and spin about Y of each seed, in order.
+    pub seeds: Vec<([f32; 3], [f32; 3], f32)>,
+    /// Between the SpineMid joints of every pair of dancers with seeds, lower body first.
+    pub distances: Vec<(usize, usize, f32)>,
+    pub state: DandelionState,
+}
+
+/// Sends the scene to another program over OSC:
+///
+/// - `/scene/seed/<n>/position x y z` and `/scene/seed/<n>/rotation x y z spin` for each
+///   seed, counting from 1, where `x y z` is where its stem points
+/// - `/scene/distance/<a>/<b> meters` for each pair of dancers with seeds, and
+///   `/scene/distance meters` for the first of them, the two lowest numbered
+/// - `/scene/brightness`, `/scene/affection`, `/scene/dancing_brightness`,
+///   `/scene/drift`, `/scene/bloom`, `/scene/grain` and `/scene/vignette`, each with its
+///   level
+/// - `/scene/swap` whenever the seeds change dancers, sent straight away
+pub struct OscOutput {
+    sock: UdpSocket,
+    destination: SocketAddr,
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl OscOutput {
+    /// Returns `None` when no destination is configured.
+    pub fn new(config: &OscOutputConfig) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let destination = match config.destination {
+            Some(destination) => destination,
+            None => return Ok(None),
+        };
+        if config.rate < 0.0 {
+            return Err(format!("OSC output rate must not be negative, not {}", config.rate).into());
+        }
+        let local: SocketAddr = if destination.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { (std::net::Ipv6Addr::UNSPECIFIED, 0).into() };
+        let sock = UdpSocket::bind(local)
+            .map_err(|e| format!("Cannot open a socket to send OSC to {}: {}", destination, e))?;
+        println!("Sending scene to {}", destination);
+        let interval = if config.rate > 0.0 { Duration::from_secs_f32(1.0 / config.rate) } else { Duration::ZERO };
+        Ok(Some(Self {
+            sock,
+            destination,
+            interval,
+            last_sent: None,
+        }))
+    }
+
+    /// Whether a report should be sent this frame, going by the configured rate.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_sent.map_or(true, |last| now.saturating_duration_since(last) >= self.interval)
+    }
+
+    pub fn send_report(&mut self, report: &SceneReport, now: Instant) {
+        self.last_sent = Some(now);
+        let mut messages = Vec::new();
+        for (i, (position, direction, spin)) in report.seeds.iter().enumerate() {
+            let [x, y, z] = *position;
+            messages.push(message(&format!("/scene/seed/{}/position", i + 1), vec![x, y, z]));
+            let [x, y, z] = *direction;
+            messages.push(message(&format!("/scene/seed/{}/rotation", i + 1), vec![x, y, z, *spin]));
+        }
+        let first = report.distances.first().map_or(0.0, |&(_, _, distance)| distance);
+        messages.push(message("/scene/distance", vec![first]));
+        for &(body, other, distance) in &report.distances {
+            messages.push(message(&format!("/scene/distance/{}/{}", body, other), vec![distance]));
+        }
+        let state = report.state;
+        messages.push(message("/scene/brightness", vec![state.brightness]));
+        messages.push(message("/scene/affection", vec![state.affection]));
+        messages.push(message("/scene/dancing_brightness", vec![state.dancing_brightness]));
+        messages.push(message("/scene/drift", vec![state.drift_strength]));
+        messages.push(message("/scene/bloom", vec![state.bloom]));
+        messages.push(message("/scene/grain", vec![state.grain]));
+        messages.push(message("/scene/vignette", vec![state.vignette]));
+        self.send(OscPacket::Bundle(OscBundle { timetag: IMMEDIATE, content: messages }));
+    }
+
+    pub fn send_swap(&self) {
+        self.send(message("/scene/swap", Vec::new()));
+    }
+
+    fn send(&self, packet: OscPacket) {
+        let buf = match rosc::encoder::encode(&packet) {
+            Ok(buf) => buf,
+            Err(e) => {
+                println!("Error encoding OSC output: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.sock.send_to(&buf, self.destination) {
+            println!("Error sending OSC to {}: {}", self.destination, e);
+        }
+    }
+}
+
+fn message(addr: &str, args: Vec<f32>) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args: args.into_iter().map(OscType::Float).collect(),
+    })
+}
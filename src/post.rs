@@ -0,0 +1,212 @@
+use eframe::{egui_glow, glow::HasContext};
+use egui_glow::glow;
+use crate::config::PostConfig;
+use crate::{create_program, DandelionState};
+
+/// Samples per pixel of the scene, as the window had before, or as many as the device
+/// allows if that is fewer.
+const SAMPLES: i32 = 8;
+
+/// A texture and the framebuffer that draws into it.
+struct Layer {
+    framebuffer: glow::Framebuffer,
+    texture: glow::Texture,
+}
+
+impl Layer {
+    unsafe fn new(gl: &glow::Context, size: [i32; 2], format: u32, pixel_type: u32) -> Self {
+        let texture = gl.create_texture().expect("Cannot create texture");
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+        gl.tex_image_2d(glow::TEXTURE_2D, 0, format as i32, size[0], size[1], 0, glow::RGBA, pixel_type, None);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        let framebuffer = gl.create_framebuffer().expect("Cannot create framebuffer");
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(texture), 0);
+        let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+        assert!(status == glow::FRAMEBUFFER_COMPLETE, "Framebuffer is not complete: {:?}", status);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        Self { framebuffer, texture }
+    }
+
+    unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_framebuffer(self.framebuffer);
+        gl.delete_texture(self.texture);
+    }
+}
+
+/// Everything one output draws into, made for its size: the scene in high dynamic range,
+/// multisampled then resolved, two half size layers the bloom is blurred between, and the
+/// finished image.
+pub struct RenderTarget {
+    pub size: [i32; 2],
+    multisampled: glow::Framebuffer,
+    color: glow::Renderbuffer,
+    scene: Layer,
+    bloom: [Layer; 2],
+    display: Layer,
+}
+
+impl RenderTarget {
+    pub fn new(gl: &glow::Context, size: [i32; 2]) -> Self {
+        unsafe {
+            let samples = SAMPLES.min(gl.get_parameter_i32(glow::MAX_SAMPLES)).max(1);
+            let color = gl.create_renderbuffer().expect("Cannot create renderbuffer");
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+            gl.renderbuffer_storage_multisample(glow::RENDERBUFFER, samples, glow::RGBA16F, size[0], size[1]);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+            let multisampled = gl.create_framebuffer().expect("Cannot create framebuffer");
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(multisampled));
+            gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::RENDERBUFFER, Some(color));
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            assert!(status == glow::FRAMEBUFFER_COMPLETE, "Framebuffer is not complete: {:?}", status);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let half = [(size[0] / 2).max(1), (size[1] / 2).max(1)];
+            Self {
+                size,
+                multisampled,
+                color,
+                scene: Layer::new(gl, size, glow::RGBA16F, glow::FLOAT),
+                bloom: [Layer::new(gl, half, glow::RGBA16F, glow::FLOAT), Layer::new(gl, half, glow::RGBA16F, glow::FLOAT)],
+                display: Layer::new(gl, size, glow::RGBA8, glow::UNSIGNED_BYTE),
+            }
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.multisampled);
+            gl.delete_renderbuffer(self.color);
+            for layer in [&self.scene, &self.bloom[0], &self.bloom[1], &self.display] {
+                layer.destroy(gl);
+            }
+        }
+    }
+
+    /// Binds the multisampled framebuffer, cleared to black, to draw the scene into.
+    pub fn begin_scene(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.multisampled));
+            gl.viewport(0, 0, self.size[0], self.size[1]);
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+    }
+
+    /// The finished image, once `PostProcess::apply` has run.
+    pub fn display_texture(&self) -> glow::Texture {
+        self.display.texture
+    }
+}
+
+/// The chain of effects between the scene and an output: the bright parts are blurred
+/// into a bloom, added back, tone mapped, vignetted and grained. Each is switched on in
+/// `[post]`, and the cue levels scale them.
+pub struct PostProcess {
+    config: PostConfig,
+    bright_program: glow::Program,
+    blur_program: glow::Program,
+    composite_program: glow::Program,
+    /// Empty, the fullscreen triangle is made up in the vertex shader.
+    vao: glow::VertexArray,
+}
+
+impl PostProcess {
+    pub fn new(gl: &glow::Context, config: &PostConfig) -> Self {
+        let vertex_shader = include_str!("./shaders/fullscreen.vs");
+        Self {
+            config: config.clone(),
+            bright_program: create_program!(vertex_shader, include_str!("./shaders/bloom_bright.fs"), gl),
+            blur_program: create_program!(vertex_shader, include_str!("./shaders/blur.fs"), gl),
+            composite_program: create_program!(vertex_shader, include_str!("./shaders/composite.fs"), gl),
+            vao: unsafe { gl.create_vertex_array().expect("Cannot create vertex array") },
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            for program in [self.bright_program, self.blur_program, self.composite_program] {
+                gl.delete_program(program);
+            }
+            gl.delete_vertex_array(self.vao);
+        }
+    }
+
+    /// Resolves the scene drawn into `target` and runs it through the effects into its
+    /// finished image, leaving that bound to draw over. The grain flickers with `time`, in
+    /// seconds of the show.
+    pub fn apply(&self, gl: &glow::Context, target: &RenderTarget, state: DandelionState, time: f32) {
+        let [width, height] = target.size;
+        let half = [(width / 2).max(1), (height / 2).max(1)];
+        let config = &self.config;
+        let bloom = if config.bloom.enabled { config.bloom.intensity * state.bloom } else { 0.0 };
+        let grain = if config.grain.enabled { config.grain.amount * state.grain } else { 0.0 };
+        let vignette = if config.vignette.enabled { config.vignette.amount * state.vignette } else { 0.0 };
+        unsafe {
+            gl.disable(glow::BLEND);
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(target.multisampled));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(target.scene.framebuffer));
+            gl.blit_framebuffer(0, 0, width, height, 0, 0, width, height, glow::COLOR_BUFFER_BIT, glow::NEAREST);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.active_texture(glow::TEXTURE0);
+
+            if bloom > 0.0 {
+                gl.viewport(0, 0, half[0], half[1]);
+                gl.use_program(Some(self.bright_program));
+                self.set_sampler(gl, self.bright_program, "image", 0);
+                gl.uniform_1_f32(gl.get_uniform_location(self.bright_program, "threshold").as_ref(), config.bloom.threshold);
+                self.pass(gl, &target.scene, &target.bloom[0]);
+
+                gl.use_program(Some(self.blur_program));
+                self.set_sampler(gl, self.blur_program, "image", 0);
+                let step_location = gl.get_uniform_location(self.blur_program, "step_size");
+                let radius = config.bloom.radius;
+                for _ in 0..config.bloom.passes {
+                    gl.uniform_2_f32(step_location.as_ref(), radius / half[0] as f32, 0.0);
+                    self.pass(gl, &target.bloom[0], &target.bloom[1]);
+                    gl.uniform_2_f32(step_location.as_ref(), 0.0, radius / half[1] as f32);
+                    self.pass(gl, &target.bloom[1], &target.bloom[0]);
+                }
+            }
+
+            let program = self.composite_program;
+            gl.viewport(0, 0, width, height);
+            gl.use_program(Some(program));
+            self.set_sampler(gl, program, "image", 0);
+            self.set_sampler(gl, program, "bloom", 1);
+            let uniform = |name: &str| gl.get_uniform_location(program, name);
+            gl.uniform_1_f32(uniform("bloom_intensity").as_ref(), bloom);
+            gl.uniform_1_f32(uniform("exposure").as_ref(), config.tonemap.exposure);
+            gl.uniform_1_i32(uniform("tonemap").as_ref(), config.tonemap.enabled as i32);
+            gl.uniform_1_f32(uniform("vignette").as_ref(), vignette);
+            gl.uniform_1_f32(uniform("vignette_softness").as_ref(), config.vignette.softness.clamp(0.01, 1.0));
+            gl.uniform_1_f32(uniform("grain").as_ref(), grain);
+            gl.uniform_1_f32(uniform("time").as_ref(), time);
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(target.bloom[0].texture));
+            gl.active_texture(glow::TEXTURE0);
+            self.pass(gl, &target.scene, &target.display);
+
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    unsafe fn set_sampler(&self, gl: &glow::Context, program: glow::Program, name: &str, unit: i32) {
+        gl.uniform_1_i32(gl.get_uniform_location(program, name).as_ref(), unit);
+    }
+
+    /// Draws `source` through the current program into `destination`.
+    unsafe fn pass(&self, gl: &glow::Context, source: &Layer, destination: &Layer) {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(destination.framebuffer));
+        gl.bind_texture(glow::TEXTURE_2D, Some(source.texture));
+        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+    }
+}
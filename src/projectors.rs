@@ -0,0 +1,338 @@
+use eframe::egui::{self, Color32};
+use eframe::{egui_glow, glow::HasContext};
+use egui_glow::glow;
+
+use crate::config::{Config, OutputConfig};
+use crate::post::{PostProcess, RenderTarget};
+use crate::scene::Scene;
+use crate::warp::Warp;
+use crate::{create_program, DandelionState};
+
+/// Quads across and down the mesh a warped image is drawn with.
+const MESH_STEPS: usize = 32;
+/// How near the pointer must be to a warp handle to pick it up, in points.
+const HANDLE_REACH: f32 = 12.0;
+
+/// The mesh a warped image is drawn with, rebuilt whenever its warp changes.
+struct WarpMesh {
+    vao: glow::VertexArray,
+    vertices: glow::Buffer,
+    indices: glow::Buffer,
+    count: i32,
+}
+
+struct Output {
+    config: OutputConfig,
+    warp: Warp,
+    /// Made on the first frame, and again whenever the output changes size.
+    target: Option<RenderTarget>,
+    mesh: WarpMesh,
+    /// The warp has changed since the mesh was built.
+    stale: bool,
+}
+
+/// Something in the warp editor that can be dragged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Handle {
+    Corner(usize),
+    Point(usize),
+}
+
+/// Splits the window between projectors. Each output draws its slice of the camera's image
+/// offscreen, runs it through the effects, darkens its edges where it overlaps its
+/// neighbours, then bends the image onto its part of the window through its warp.
+pub struct Projectors {
+    outputs: Vec<Output>,
+    post: PostProcess,
+    blend_program: glow::Program,
+    /// Empty, the blend triangle is made up in the vertex shader.
+    blend_vao: glow::VertexArray,
+    viewport: Option<glow::UniformLocation>,
+    edges: Option<glow::UniformLocation>,
+    curve: Option<glow::UniformLocation>,
+    gamma: Option<glow::UniformLocation>,
+    warp_program: glow::Program,
+    /// The handle being dragged in the warp editor, and the output it belongs to.
+    dragging: Option<(usize, Handle)>,
+}
+
+impl Projectors {
+    /// Without any outputs the whole window shows the whole image. Outputs without a warp in
+    /// `warps` start unwarped.
+    pub fn new(gl: &glow::Context, config: &Config, warps: &[Warp]) -> Self {
+        let configs = if config.outputs.is_empty() { vec![OutputConfig::default()] } else { config.outputs.clone() };
+        let grid = config.warp.grid;
+        let blend_program = create_program!(include_str!("./shaders/edge_blend.vs"), include_str!("./shaders/edge_blend.fs"), gl);
+        let warp_program = create_program!(include_str!("./shaders/warp.vs"), include_str!("./shaders/warp.fs"), gl);
+        let outputs = configs
+            .into_iter()
+            .enumerate()
+            .map(|(i, config)| Output {
+                config,
+                warp: warps.get(i).cloned().unwrap_or_else(|| Warp::new(grid[0], grid[1])),
+                target: None,
+                mesh: unsafe { Self::create_mesh(gl, warp_program) },
+                stale: true,
+            })
+            .collect();
+        unsafe {
+            Self {
+                outputs,
+                post: PostProcess::new(gl, &config.post),
+                blend_program,
+                blend_vao: gl.create_vertex_array().expect("Cannot create vertex array"),
+                viewport: gl.get_uniform_location(blend_program, "viewport"),
+                edges: gl.get_uniform_location(blend_program, "edges"),
+                curve: gl.get_uniform_location(blend_program, "curve"),
+                gamma: gl.get_uniform_location(blend_program, "gamma"),
+                warp_program,
+                dragging: None,
+            }
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.blend_program);
+            gl.delete_program(self.warp_program);
+            gl.delete_vertex_array(self.blend_vao);
+            for output in &self.outputs {
+                gl.delete_vertex_array(output.mesh.vao);
+                gl.delete_buffer(output.mesh.vertices);
+                gl.delete_buffer(output.mesh.indices);
+                if let Some(target) = &output.target {
+                    target.destroy(gl);
+                }
+            }
+        }
+        self.post.destroy(gl);
+    }
+
+    unsafe fn create_mesh(gl: &glow::Context, program: glow::Program) -> WarpMesh {
+        let vao = gl.create_vertex_array().expect("Cannot create vertex array");
+        let vertices = gl.create_buffer().expect("Cannot create buffer");
+        let indices = gl.create_buffer().expect("Cannot create buffer");
+        gl.bind_vertex_array(Some(vao));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertices));
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(indices));
+        let stride = 4 * std::mem::size_of::<f32>() as i32;
+        if let Some(position) = gl.get_attrib_location(program, "position") {
+            gl.enable_vertex_attrib_array(position);
+            gl.vertex_attrib_pointer_f32(position, 2, glow::FLOAT, false, stride, 0);
+        }
+        if let Some(texcoord) = gl.get_attrib_location(program, "texcoord") {
+            gl.enable_vertex_attrib_array(texcoord);
+            gl.vertex_attrib_pointer_f32(texcoord, 2, glow::FLOAT, false, stride, 2 * std::mem::size_of::<f32>() as i32);
+        }
+        gl.bind_vertex_array(None);
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+        WarpMesh { vao, vertices, indices, count: 0 }
+    }
+
+    /// Paints every output into `window`, given as [x, y, width, height] in pixels from the
+    /// bottom left, and leaves `framebuffer` bound with the GL viewport on the whole window.
+    pub fn paint(&mut self, gl: &glow::Context, framebuffer: Option<glow::Framebuffer>, window: [i32; 4], scene: &Scene, state: DandelionState) {
+        let [x, y, width, height] = window;
+        for i in 0..self.outputs.len() {
+            let [left, top, share_x, share_y] = self.outputs[i].config.viewport;
+            let view = [
+                x + (left * width as f32).round() as i32,
+                y + ((1.0 - top - share_y) * height as f32).round() as i32,
+                (share_x * width as f32).round() as i32,
+                (share_y * height as f32).round() as i32,
+            ];
+            if view[2] <= 0 || view[3] <= 0 {
+                continue;
+            }
+            unsafe {
+                self.draw_output(gl, i, [view[2], view[3]], scene, state);
+                gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+                gl.viewport(view[0], view[1], view[2], view[3]);
+                self.draw_warped(gl, i);
+            }
+        }
+        unsafe {
+            gl.viewport(x, y, width, height);
+        }
+    }
+
+    /// Draws the output's slice of the scene into its offscreen image, `size` pixels.
+    unsafe fn draw_output(&mut self, gl: &glow::Context, index: usize, size: [i32; 2], scene: &Scene, state: DandelionState) {
+        let output = &mut self.outputs[index];
+        if output.target.as_ref().map_or(true, |target| target.size != size) {
+            if let Some(target) = output.target.take() {
+                target.destroy(gl);
+            }
+            output.target = Some(RenderTarget::new(gl, size));
+        }
+        let output = &self.outputs[index];
+        let target = output.target.as_ref().unwrap();
+        let [slice_left, slice_bottom, slice_right, slice_top] = output.config.frustum;
+        // the whole image is as much wider than the slice as the slice is of it
+        let aspect = (size[0] as f32 / size[1] as f32) * (slice_top - slice_bottom) / (slice_right - slice_left);
+        let projection = scene.camera.projection_matrix(aspect, output.config.frustum);
+
+        // the window's scissor box means nothing here
+        gl.disable(glow::SCISSOR_TEST);
+        target.begin_scene(gl);
+        // the scene is drawn with egui's premultiplied blending, as it was in the window
+        gl.enable(glow::BLEND);
+        gl.blend_func_separate(glow::ONE, glow::ONE_MINUS_SRC_ALPHA, glow::ONE_MINUS_DST_ALPHA, glow::ONE);
+        scene.paint(gl, &projection, state);
+        self.post.apply(gl, target, state, scene.elapsed());
+        self.blend_edges(gl, &output.config, size);
+        gl.enable(glow::SCISSOR_TEST);
+    }
+
+    unsafe fn blend_edges(&self, gl: &glow::Context, output: &OutputConfig, size: [i32; 2]) {
+        let blend = &output.blend;
+        if blend.left <= 0.0 && blend.right <= 0.0 && blend.top <= 0.0 && blend.bottom <= 0.0 {
+            return;
+        }
+        gl.enable(glow::BLEND);
+        gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+        gl.use_program(Some(self.blend_program));
+        gl.uniform_4_f32(self.viewport.as_ref(), 0.0, 0.0, size[0] as f32, size[1] as f32);
+        gl.uniform_4_f32(self.edges.as_ref(), blend.left, blend.right, blend.top, blend.bottom);
+        gl.uniform_1_f32(self.curve.as_ref(), blend.curve.max(1.0));
+        gl.uniform_1_f32(self.gamma.as_ref(), blend.gamma.max(0.1));
+        gl.bind_vertex_array(Some(self.blend_vao));
+        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        gl.bind_vertex_array(None);
+    }
+
+    /// Draws the output's image through its warp into the current viewport.
+    unsafe fn draw_warped(&mut self, gl: &glow::Context, index: usize) {
+        let output = &mut self.outputs[index];
+        if output.stale {
+            let (vertices, indices) = output.warp.mesh(MESH_STEPS);
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(output.mesh.vertices));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytemuck::cast_slice(&vertices), glow::STATIC_DRAW);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            // the element buffer is part of the vertex array's state
+            gl.bind_vertex_array(Some(output.mesh.vao));
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(output.mesh.indices));
+            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, bytemuck::cast_slice(&indices), glow::STATIC_DRAW);
+            gl.bind_vertex_array(None);
+            output.mesh.count = indices.len() as i32;
+            output.stale = false;
+        }
+        let texture = output.target.as_ref().map(|target| target.display_texture());
+        gl.disable(glow::BLEND);
+        gl.use_program(Some(self.warp_program));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, texture);
+        gl.uniform_1_i32(gl.get_uniform_location(self.warp_program, "image").as_ref(), 0);
+        gl.bind_vertex_array(Some(output.mesh.vao));
+        gl.draw_elements(glow::TRIANGLES, output.mesh.count, glow::UNSIGNED_INT, 0);
+        gl.bind_vertex_array(None);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+    }
+
+    pub fn warps(&self) -> Vec<Warp> {
+        self.outputs.iter().map(|output| output.warp.clone()).collect()
+    }
+
+    /// The warp editor: drags the corners and grid points of the outputs' warps with the
+    /// mouse, and R puts back the warp of the output under the pointer. Draws the handles
+    /// over `rect`, the part of the window the outputs share.
+    pub fn edit_warps(&mut self, ui: &egui::Ui, rect: egui::Rect) {
+        let (pointer, pressed, down, reset) = ui.input(|i| {
+            (i.pointer.interact_pos(), i.pointer.primary_pressed(), i.pointer.primary_down(), i.key_pressed(egui::Key::R))
+        });
+        let areas: Vec<egui::Rect> = self.outputs.iter().map(|output| output_rect(rect, &output.config)).collect();
+
+        if let Some(pointer) = pointer {
+            if pressed {
+                self.dragging = self.handle_at(&areas, pointer);
+            }
+            if reset {
+                if let Some(index) = areas.iter().position(|area| area.contains(pointer)) {
+                    let warp = &mut self.outputs[index].warp;
+                    *warp = Warp::new(warp.columns, warp.rows());
+                    self.outputs[index].stale = true;
+                }
+            }
+            if let Some((index, handle)) = self.dragging.filter(|_| down) {
+                let area = areas[index];
+                let position = [(pointer.x - area.min.x) / area.width(), (pointer.y - area.min.y) / area.height()];
+                let output = &mut self.outputs[index];
+                match handle {
+                    Handle::Corner(corner) => output.warp.corners[corner] = position,
+                    Handle::Point(point) => {
+                        if let Some(unpinned) = output.warp.unpin(position) {
+                            output.warp.points[point] = unpinned;
+                        }
+                    }
+                }
+                output.stale = true;
+            }
+        }
+        if !down {
+            self.dragging = None;
+        }
+
+        let painter = ui.painter();
+        for (output, area) in self.outputs.iter().zip(&areas) {
+            let warp = &output.warp;
+            let at = |p: [f32; 2]| egui::pos2(area.min.x + p[0] * area.width(), area.min.y + p[1] * area.height());
+            let line = egui::Stroke::new(1.0, Color32::from_gray(160));
+            for row in 0..warp.rows() {
+                for column in 0..warp.columns {
+                    let point = at(warp.pin(warp.points[row * warp.columns + column]));
+                    if column + 1 < warp.columns {
+                        painter.line_segment([point, at(warp.pin(warp.points[row * warp.columns + column + 1]))], line);
+                    }
+                    if row + 1 < warp.rows() {
+                        painter.line_segment([point, at(warp.pin(warp.points[(row + 1) * warp.columns + column]))], line);
+                    }
+                }
+            }
+            for handle in handles(warp) {
+                let (position, radius, color) = match handle {
+                    Handle::Corner(corner) => (at(warp.corners[corner]), 8.0, Color32::from_rgb(255, 160, 0)),
+                    Handle::Point(point) => (at(warp.pin(warp.points[point])), 5.0, Color32::WHITE),
+                };
+                painter.circle_stroke(position, radius, egui::Stroke::new(2.0, color));
+            }
+        }
+    }
+
+    /// The nearest handle within reach of `pointer`, corners first.
+    fn handle_at(&self, areas: &[egui::Rect], pointer: egui::Pos2) -> Option<(usize, Handle)> {
+        let mut nearest = None;
+        let mut best = HANDLE_REACH;
+        for (index, (output, area)) in self.outputs.iter().zip(areas).enumerate() {
+            let warp = &output.warp;
+            for handle in handles(warp) {
+                let position = match handle {
+                    Handle::Corner(corner) => warp.corners[corner],
+                    Handle::Point(point) => warp.pin(warp.points[point]),
+                };
+                let distance = egui::pos2(area.min.x + position[0] * area.width(), area.min.y + position[1] * area.height()).distance(pointer);
+                if distance < best {
+                    best = distance;
+                    nearest = Some((index, handle));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+/// The corners, then every grid point but those under the corners.
+fn handles(warp: &Warp) -> impl Iterator<Item = Handle> {
+    let (columns, rows) = (warp.columns, warp.rows());
+    let corners = [0, columns - 1, rows * columns - 1, (rows - 1) * columns];
+    (0..4).map(Handle::Corner).chain((0..rows * columns).filter(move |i| !corners.contains(i)).map(Handle::Point))
+}
+
+/// The part of `window` an output covers.
+fn output_rect(window: egui::Rect, config: &OutputConfig) -> egui::Rect {
+    let [left, top, width, height] = config.viewport;
+    egui::Rect::from_min_size(
+        egui::pos2(window.min.x + left * window.width(), window.min.y + top * window.height()),
+        egui::vec2(width * window.width(), height * window.height()),
+    )
+}
@@ -0,0 +1,114 @@
+use eframe::egui::{self, mutex::Mutex};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::body_source::{BodySink, BodySource};
+use crate::skeleton::{Joint, JointUpdate};
+
+/// Meters a body moves per pixel of mouse drag.
+const DRAG_SCALE: f32 = 0.01;
+/// Meters per second a body walks while I, J, K or L is held.
+const WALK_SPEED: f32 = 1.0;
+const HEAD_HEIGHT: f32 = 0.2;
+
+struct PuppetBody {
+    body: usize,
+    position: [f32; 3],
+    /// Horizontal offset of the head from the spine, for leaning.
+    lean: [f32; 2],
+}
+
+struct PuppetState {
+    bodies: Vec<PuppetBody>,
+    selected: usize,
+}
+
+/// Bodies moved by hand from the app, for rehearsing without a tracker.
+pub struct PuppetSource {
+    state: Arc<Mutex<PuppetState>>,
+}
+
+/// Lets the app move the puppet bodies.
+pub struct PuppetHandle {
+    state: Arc<Mutex<PuppetState>>,
+}
+
+impl PuppetSource {
+    /// Starts the bodies side by side in the middle of the stage.
+    pub fn new(bodies: Vec<usize>) -> (Self, PuppetHandle) {
+        let spacing = 1.0;
+        let left = -spacing * (bodies.len() as f32 - 1.0) / 2.0;
+        let bodies = bodies.into_iter().enumerate()
+            .map(|(i, body)| PuppetBody {
+                body,
+                position: [left + spacing * i as f32, 0.0, -2.0],
+                lean: [0.0; 2],
+            })
+            .collect();
+        let state = Arc::new(Mutex::new(PuppetState { bodies, selected: 0 }));
+        (Self { state: state.clone() }, PuppetHandle { state })
+    }
+}
+
+impl BodySource for PuppetSource {
+    fn start(self: Box<Self>, sink: BodySink) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::spawn(move || loop {
+            let updates = self.state.lock().bodies.iter()
+                .flat_map(|puppet| {
+                    let [x, y, z] = puppet.position;
+                    let [lean_x, lean_z] = puppet.lean;
+                    [
+                        JointUpdate { body: puppet.body, joint: Joint::SpineMid, position: puppet.position },
+                        JointUpdate { body: puppet.body, joint: Joint::Head, position: [x + lean_x, y + HEAD_HEIGHT, z + lean_z] },
+                    ]
+                })
+                .collect::<Vec<_>>();
+            sink.apply(&updates, Instant::now());
+            std::thread::sleep(Duration::from_millis(10));
+        });
+        Ok(())
+    }
+}
+
+impl PuppetHandle {
+    /// Tab selects the next body. Dragging with the left mouse button or holding I, J, K
+    /// and L walks the selected body across the stage, dragging with the right button
+    /// leans its head.
+    pub fn handle_input(&self, ui: &egui::Ui) {
+        let (tab, drag, primary, secondary, walk, dt) = ui.input(|i| {
+            let mut walk = [0.0f32; 2];
+            for (key, x, z) in [(egui::Key::J, -1.0, 0.0), (egui::Key::L, 1.0, 0.0), (egui::Key::I, 0.0, -1.0), (egui::Key::K, 0.0, 1.0)] {
+                if i.key_down(key) {
+                    walk[0] += x;
+                    walk[1] += z;
+                }
+            }
+            (i.key_pressed(egui::Key::Tab), i.pointer.delta(), i.pointer.primary_down(), i.pointer.secondary_down(), walk, i.stable_dt)
+        });
+
+        let mut state = self.state.lock();
+        if tab {
+            state.selected = (state.selected + 1) % state.bodies.len();
+        }
+        let selected = state.selected;
+        let puppet = &mut state.bodies[selected];
+        // dragging up moves upstage, away from the audience
+        if primary {
+            puppet.position[0] += drag.x * DRAG_SCALE;
+            puppet.position[2] += drag.y * DRAG_SCALE;
+        }
+        if secondary {
+            puppet.lean[0] += drag.x * DRAG_SCALE;
+            puppet.lean[1] += drag.y * DRAG_SCALE;
+        }
+        puppet.position[0] += walk[0] * WALK_SPEED * dt;
+        puppet.position[2] += walk[1] * WALK_SPEED * dt;
+    }
+
+    pub fn status(&self) -> String {
+        let state = self.state.lock();
+        let puppet = &state.bodies[state.selected];
+        let [x, y, z] = puppet.position;
+        format!("Puppet: body {} at ({:.2}, {:.2}, {:.2})", puppet.body, x, y, z)
+    }
+}
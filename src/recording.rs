@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::body_source::{BodySink, BodySource};
+use crate::config::{ReplayConfig, ReplayMode};
+use crate::skeleton::{Joint, JointUpdate};
+
+const HEADER: &str = "# dandelions tracker recording: seconds body joint x y z";
+
+/// Writes every tracker frame to a text file, one joint per line, in stage coordinates
+/// (after calibration). Lines with the same time belong to the same frame.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::create(path)
+            .map_err(|e| format!("Cannot create recording {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Self {
+            writer,
+            start: None,
+        })
+    }
+
+    pub fn record(&mut self, time: Instant, updates: &[JointUpdate]) -> Result<(), Box<dyn std::error::Error>> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let start = *self.start.get_or_insert(time);
+        let seconds = time.saturating_duration_since(start).as_secs_f64();
+        for update in updates {
+            let [x, y, z] = update.position;
+            writeln!(self.writer, "{:.6} {} {} {} {} {}", seconds, update.body, update.joint.name(), x, y, z)?;
+        }
+        // flushed per frame so that a show that is killed still leaves a usable file
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct RecordedFrame {
+    pub time: f64,
+    pub updates: Vec<JointUpdate>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<RecordedFrame>, Box<dyn std::error::Error>> {
+    let file = File::open(path)
+        .map_err(|e| format!("Cannot open recording {}: {}", path.display(), e))?;
+    let mut frames: Vec<RecordedFrame> = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (time, update) = parse_line(line)
+            .ok_or_else(|| format!("{}:{}: expected \"seconds body joint x y z\"", path.display(), number + 1))?;
+        match frames.last_mut() {
+            Some(frame) if frame.time == time => frame.updates.push(update),
+            _ => frames.push(RecordedFrame { time, updates: vec![update] }),
+        }
+    }
+    if frames.is_empty() {
+        return Err(format!("recording {} has no frames", path.display()).into());
+    }
+    Ok(frames)
+}
+
+fn parse_line(line: &str) -> Option<(f64, JointUpdate)> {
+    let mut fields = line.split_whitespace();
+    let time = fields.next()?.parse().ok()?;
+    let body = fields.next()?.parse().ok()?;
+    let joint = Joint::from_name(fields.next()?)?;
+    let mut position = [0.0; 3];
+    for value in position.iter_mut() {
+        *value = fields.next()?.parse().ok()?;
+    }
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((time, JointUpdate { body, joint, position }))
+}
+
+/// Lets the app advance a replay running in `ReplayMode::Step`.
+pub struct ReplayHandle {
+    step: Sender<()>,
+}
+
+impl ReplayHandle {
+    pub fn step(&self) {
+        // the replay thread only goes away with the app
+        let _ = self.step.send(());
+    }
+}
+
+/// Feeds a recording into the skeleton, looping at the end.
+pub struct ReplaySource {
+    frames: Vec<RecordedFrame>,
+    mode: ReplayMode,
+    speed: f64,
+    steps: Receiver<()>,
+}
+
+impl ReplaySource {
+    pub fn new(config: &ReplayConfig) -> Result<(Self, ReplayHandle), Box<dyn std::error::Error>> {
+        let path = config.file.as_ref().ok_or("no recording to replay, set one with --replay")?;
+        let frames = load(path)?;
+        if config.speed <= 0.0 {
+            return Err(format!("replay speed must be positive, not {}", config.speed).into());
+        }
+        println!("Replaying {} ({} frames)", path.display(), frames.len());
+
+        let (step, steps) = mpsc::channel();
+        let source = Self {
+            frames,
+            mode: config.mode,
+            speed: config.speed,
+            steps,
+        };
+        Ok((source, ReplayHandle { step }))
+    }
+}
+
+impl BodySource for ReplaySource {
+    fn start(self: Box<Self>, sink: BodySink) -> Result<(), Box<dyn std::error::Error>> {
+        let ReplaySource { frames, mode, speed, steps } = *self;
+        std::thread::spawn(move || match mode {
+            ReplayMode::Realtime => replay_realtime(&frames, speed, &sink),
+            ReplayMode::Step => replay_steps(&frames, steps, &sink),
+        });
+        Ok(())
+    }
+}
+
+fn replay_realtime(frames: &[RecordedFrame], speed: f64, sink: &BodySink) {
+    loop {
+        let start = Instant::now();
+        let first = frames[0].time;
+        for frame in frames {
+            let due = start + Duration::from_secs_f64((frame.time - first).max(0.0) / speed);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            sink.apply(&frame.updates, Instant::now());
+        }
+    }
+}
+
+/// Holds each frame until the next step, re-sending it meanwhile so the bodies are not
+/// considered lost while paused.
+fn replay_steps(frames: &[RecordedFrame], steps: Receiver<()>, sink: &BodySink) {
+    for frame in frames.iter().cycle() {
+        loop {
+            sink.apply(&frame.updates, Instant::now());
+            match steps.recv_timeout(Duration::from_millis(100)) {
+                Ok(()) => break,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use eframe::{egui_glow, glow::HasContext};
+use egui_glow::glow;
+use glutin::api::egl::{context::PossiblyCurrentContext, device::Device, display::Display};
+use glutin::config::{Api, ConfigSurfaceTypes, ConfigTemplateBuilder};
+use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
+use glutin::display::GlDisplay;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::body_source;
+use crate::clock::Clock;
+use crate::config::{Config, SourceKind};
+use crate::cues::{Cue, CuePlayer};
+use crate::dancer_mock::{MockDancers, MockSource};
+use crate::filter::FilterPipeline;
+use crate::projectors::Projectors;
+use crate::recording;
+use crate::scene::Scene;
+use crate::warp::Warp;
+use crate::SKELETON;
+
+/// Plays the show without a window, writing every frame to a numbered PNG in
+/// `[render] output`. Time moves on by exactly one frame at a time however long a frame
+/// takes to draw: the recording is fed in as its timestamps come due, a mock source walks
+/// its dancers along and GO is pressed at the `go` times. The audio is not analysed. With
+/// the same recording, cues and `[random] seed`, two renders give the same frames.
+pub fn render(config: &Config, filters: FilterPipeline, cues: Vec<Cue>, warps: &[Warp]) -> Result<(), Box<dyn std::error::Error>> {
+    let render = &config.render;
+    let output = render.output.as_deref().ok_or("no render output directory")?;
+    let [width, height] = render.size;
+    if width == 0 || height == 0 || render.fps <= 0.0 {
+        return Err("render needs a size and a frame rate above 0".into());
+    }
+    let frames = match &config.replay.file {
+        Some(file) => recording::load(file)?,
+        None => Vec::new(),
+    };
+    let first = frames.first().map_or(0.0, |frame| frame.time);
+    let duration = match render.duration {
+        Some(duration) => duration as f64,
+        None => match frames.last() {
+            Some(last) => last.time - first,
+            None => return Err("nothing sets how long to render: give a recording with --replay or --render-duration".into()),
+        },
+    };
+    std::fs::create_dir_all(output)
+        .map_err(|e| format!("Cannot create {}: {}", output.display(), e))?;
+
+    let (gl, _context) = create_context()?;
+    let (width, height) = (width as i32, height as i32);
+    let (framebuffer, texture) = unsafe {
+        let texture = gl.create_texture().expect("Cannot create texture");
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::RGBA8 as i32, width, height, 0, glow::RGBA, glow::UNSIGNED_BYTE, None);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        let framebuffer = gl.create_framebuffer().expect("Cannot create framebuffer");
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(texture), 0);
+        let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+        assert!(status == glow::FRAMEBUFFER_COMPLETE, "Framebuffer is not complete: {:?}", status);
+        (framebuffer, texture)
+    };
+
+    let mut clock = Clock::fixed(Duration::from_secs_f64(1.0 / render.fps as f64));
+    let start = clock.start();
+    let mut mocks = Vec::new();
+    for (source, sink) in config.sources.iter().zip(body_source::sinks(config)?) {
+        if source.kind == SourceKind::Mock {
+            let dancers = MockDancers::new(MockSource::new(&config.mock, config.random.seed)?, &sink, start);
+            mocks.push((dancers, sink));
+        }
+    }
+    let mut scene = Scene::new(&gl, config, filters, None, &clock);
+    let mut projectors = Projectors::new(&gl, config, warps);
+    let mut cues = CuePlayer::new(cues);
+    let mut next_frame = 0;
+    let mut next_go = 0;
+    let count = (duration * render.fps as f64).ceil() as usize;
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    for number in 0..count {
+        let now = clock.now();
+        let seconds = clock.elapsed();
+        while next_frame < frames.len() && frames[next_frame].time - first <= seconds {
+            let frame = &frames[next_frame];
+            SKELETON.lock().apply(&frame.updates, start + Duration::from_secs_f64(frame.time - first));
+            next_frame += 1;
+        }
+        for (dancers, sink) in &mut mocks {
+            dancers.advance(sink, now);
+        }
+        while next_go < render.go.len() && render.go[next_go] as f64 <= seconds {
+            cues.go(now);
+            next_go += 1;
+        }
+
+        let state = cues.state(now);
+        for trigger in cues.take_triggers() {
+            scene.trigger(trigger);
+        }
+        scene.update(state, now);
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.viewport(0, 0, width, height);
+            gl.scissor(0, 0, width, height);
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            projectors.paint(&gl, Some(framebuffer), [0, 0, width, height], &scene, state);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.read_pixels(0, 0, width, height, glow::RGBA, glow::UNSIGNED_BYTE, glow::PixelPackData::Slice(&mut pixels));
+        }
+        let path = output.join(format!("frame_{:06}.png", number + 1));
+        write_png(&path, &pixels, width as usize, height as usize)?;
+        if (number + 1) % render.fps.ceil().max(1.0) as usize == 0 || number + 1 == count {
+            println!("Rendered {} of {} frames", number + 1, count);
+        }
+        clock.tick();
+    }
+    scene.destroy(&gl);
+    projectors.destroy(&gl);
+    unsafe {
+        gl.delete_framebuffer(framebuffer);
+        gl.delete_texture(texture);
+    }
+    Ok(())
+}
+
+/// An OpenGL 4.3 context on the first EGL device, current without any surface.
+fn create_context() -> Result<(glow::Context, PossiblyCurrentContext), Box<dyn std::error::Error>> {
+    let device = Device::query_devices()
+        .map_err(|e| format!("Cannot list EGL devices: {}", e))?
+        .next()
+        .ok_or("Cannot find an EGL device to render with")?;
+    let display = unsafe { Display::with_device(&device, None) }
+        .map_err(|e| format!("Cannot open the EGL device: {}", e))?;
+    let template = ConfigTemplateBuilder::new()
+        .with_surface_type(ConfigSurfaceTypes::empty())
+        .with_api(Api::OPENGL)
+        .build();
+    let gl_config = unsafe { display.find_configs(template) }
+        .map_err(|e| format!("Cannot find an EGL config: {}", e))?
+        .next()
+        .ok_or("Cannot find an EGL config for OpenGL")?;
+    let attributes = ContextAttributesBuilder::new()
+        .with_context_api(ContextApi::OpenGl(Some(Version::new(4, 3))))
+        .build(None);
+    let context = unsafe { display.create_context(&gl_config, &attributes) }
+        .map_err(|e| format!("Cannot create an OpenGL 4.3 context: {}", e))?
+        .make_current_surfaceless()
+        .map_err(|e| format!("Cannot make the OpenGL context current: {}", e))?;
+    let gl = unsafe { glow::Context::from_loader_function_cstr(|name| display.get_proc_address(name)) };
+    Ok((gl, context))
+}
+
+/// Writes bottom up RGBA rows, as GL reads them, to a top down PNG.
+fn write_png(path: &Path, pixels: &[u8], width: usize, height: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let row = width * 4;
+    let flipped: Vec<u8> = pixels.chunks_exact(row).rev().flatten().copied().collect();
+    writer.write_image_data(&flipped)?;
+    Ok(())
+}
@@ -3,47 +3,115 @@ use eframe::epaint::text;
 use eframe::{egui_glow, glow::HasContext, egui};
 use egui_glow::glow;
 use std::f32::consts::PI;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 use rand::prelude::*;
-use rand::rngs::{OsRng, StdRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::color::Color;
-use crate::dandelion::DandelionSeed;
+use crate::dandelion::{DandelionSeed, SeedRenderer};
 use crate::ground::{self, Ground};
 use crate::affine_matrix::AffineMatrix;
-use crate::{DandelionState, BODY1_BASE_SPINE, BODY1_HEAD, BODY2_BASE_SPINE, BODY2_HEAD};
+use crate::config::{Config, FlightConfig, LostBehavior, SeedBehavior, TrackingConfig};
+use crate::filter::FilterPipeline;
+use crate::skeleton::{Joint, TrackingState};
+use crate::osc_output::{OscOutput, SceneReport};
+use crate::transition::blend_factor;
+use crate::audio::AudioMapper;
+use crate::assignment;
+use crate::cues::Trigger;
+use crate::seed_head::SeedHead;
+use crate::wind::WindField;
+use crate::flight::{Flight, Steering};
+use crate::camera::Camera;
+use crate::clock::Clock;
+use crate::{DandelionState, AUDIO, SKELETON};
+
+/// Height of the ground the seeds settle on.
+pub const GROUND_HEIGHT: f32 = -1.0;
+/// Speed the ground slides along X, in meters per second.
+pub const GROUND_SPEED: f32 = 0.5;
+/// The ground tiles slide from -GROUND_EXTENT to GROUND_EXTENT along X.
+pub const GROUND_EXTENT: f32 = 16.0;
+/// Seconds over which a seed's spin forgets which way it was turning.
+const SPIN_TIME: f32 = 1.66;
+/// Typical speed of a seed's spin, in radians per second.
+const SPIN_SPEED: f32 = 2.45;
+/// Height above an idle seed's position of the head it points to, in meters.
+const IDLE_HEAD_HEIGHT: f32 = 0.2;
 
 pub trait Paintable {
-    fn paint(&self, gl: &glow::Context, screen_size: (f32, f32), view_matrix: &AffineMatrix);
+    fn paint(&self, gl: &glow::Context, view_matrix: &AffineMatrix, projection: &AffineMatrix);
+}
+
+/// A seed and what it does.
+struct Seed {
+    model: DandelionSeed,
+    behavior: SeedBehavior,
+    /// Its flight, when seeds fly under `[flight]`.
+    flight: Option<Flight>,
+}
+
+impl Seed {
+    /// Moves the seed after `steering.target` with its stem along `steering.aim`, letting
+    /// go as the steering weight drops. Without flight it closes `follow` of the way each
+    /// update.
+    fn follow(&mut self, rng: &mut ChaCha8Rng, wind: &WindField, config: &FlightConfig, steering: Steering, follow: f32, dt: f32) {
+        match self.flight.as_mut() {
+            Some(flight) => {
+                flight.advance(config, wind, Some(steering), dt);
+                let [x, y, z] = flight.position;
+                self.model.translation.set_translate(x, y, z);
+                self.model.rotation = flight.rotation();
+                self.model.theta = flight.spin;
+            }
+            None => {
+                Scene::update_dandelion(&mut self.model, rng, wind, steering, follow, dt);
+            }
+        }
+    }
 }
 
 pub struct Scene {
     time: Instant,
-    dandelion_seed1: DandelionSeed,
-    dandelion_seed2: DandelionSeed,
-
-    dancing_dandelion1: DandelionSeed,
-    dancing_dandelion2: DandelionSeed,
+    seeds: Vec<Seed>,
+    seed_renderer: SeedRenderer,
+    seed_head: Option<SeedHead>,
+    wind: WindField,
+    flight: FlightConfig,
     ground: Ground,
     ground_mirror: Ground,
     ground_2: Ground,
     ground_mirror_2: Ground,
-    rng: rand::rngs::OsRng,
-    frame_buffer: glow::Framebuffer,
-    texture: glow::Texture,
-    view_port: (f32, f32),
-    pub camera_pos: [f32; 3],
+    /// Seeded from `[random]`, so the same run wobbles the same way.
+    rng: ChaCha8Rng,
+    pub camera: Camera,
+    filters: FilterPipeline,
+    tracking: TrackingConfig,
+    /// How much each body's seed is following it, from 0 (gone) to 1 (tracked).
+    presence: HashMap<usize, f32>,
+    last_update: Instant,
+    output: Option<OscOutput>,
+    audio: AudioMapper,
+    /// Angle of the dancing duet, which turns faster or slower with the audio.
+    dance_phase: f32,
 }
 
 impl Scene {
-    pub fn new(gl: &glow::Context) -> Self {
-        let mut dandelion_seed1 = DandelionSeed::new(gl);
-        dandelion_seed1.translation.set_translate(0.0, 0.0, -2.0);
-        dandelion_seed1.scale.set_scale(0.04, 0.04, 0.04);
-
-        let mut dandelion_seed2 = DandelionSeed::new(gl);
-        dandelion_seed2.translation.set_translate(0.0, 0.0, -2.0);
-        dandelion_seed2.scale.set_scale(0.04, 0.04, 0.04);
+    /// A scene starting at the start of `clock`; every later update says what time it is.
+    pub fn new(gl: &glow::Context, config: &Config, filters: FilterPipeline, output: Option<OscOutput>, clock: &Clock) -> Self {
+        let seeds = config.seeds.iter().map(|&behavior| {
+            let mut model = DandelionSeed::new();
+            match behavior {
+                SeedBehavior::Dance => {}
+                SeedBehavior::Idle { position } => model.translation.set_translate(position[0], position[1], position[2]),
+                SeedBehavior::Follow { .. } | SeedBehavior::Drift { .. } => model.translation.set_translate(0.0, 0.0, -2.0),
+            }
+            model.scale.set_scale(0.04, 0.04, 0.04);
+            let flight = (config.flight.enabled && behavior != SeedBehavior::Dance)
+                .then(|| Flight::new(model.get_position(), [0.0, 1.0, 0.0]));
+            Seed { model, behavior, flight }
+        }).collect();
 
         let ground = Ground::new(gl);
         let mut ground_mirror = Ground::new(gl);
@@ -51,85 +119,42 @@ impl Scene {
         let ground_2 = Ground::new(gl);
         let mut ground_mirror_2 = Ground::new(gl);
         ground_mirror_2.scale.set_scale(-1.0, 1.0, 1.0);
-        let rng = rand::rngs::OsRng::default();
-        let texture = unsafe {
-            let texture = gl.create_texture().expect("Failed to create texture");
-            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
-
-            // Set texture parameters (same as before)
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
-            gl.tex_image_2d(
-                glow::TEXTURE_2D,
-                0,
-                glow::RGBA as i32,
-                100 as i32,
-                100 as i32,
-                0,
-                glow::RGBA,
-                glow::UNSIGNED_BYTE,
-                None,
-            );
-            texture
-        };
-        let fbo = unsafe {
-            let fbo = gl.create_framebuffer().expect("Failed to create FBO");
-            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
-
-            // Attach the texture to the FBO
-            gl.framebuffer_texture_2d(
-                glow::FRAMEBUFFER,
-                glow::COLOR_ATTACHMENT0,
-                glow::TEXTURE_2D,
-                Some(texture),
-                0,
-            );
-            // Check the FBO status
-            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
-            if status != glow::FRAMEBUFFER_COMPLETE {
-                panic!("Framebuffer is not complete: {:?}", status);
-            }
-            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
-            fbo
-        };
-
-        let mut dancing_dandelion1 = DandelionSeed::new(gl);
-        dancing_dandelion1.scale.set_scale(0.04, 0.04, 0.04);
-        let mut dancing_dandelion2 = DandelionSeed::new(gl);
-        dancing_dandelion2.scale.set_scale(0.04, 0.04, 0.04);
-
-
+        let mut rng = ChaCha8Rng::seed_from_u64(config.random.seed);
+        let seed_head_rng = ChaCha8Rng::seed_from_u64(rng.gen());
         Self {
-            time: Instant::now(),
-            dandelion_seed1,
-            dandelion_seed2,
-            dancing_dandelion1,
-            dancing_dandelion2,
+            time: clock.start(),
+            seeds,
+            seed_renderer: SeedRenderer::new(gl),
+            seed_head: config.seed_head.enabled.then(|| SeedHead::new(config.seed_head.clone(), config.flight.enabled.then(|| config.flight.clone()), seed_head_rng)),
+            wind: WindField::new(config.wind.clone()),
+            flight: config.flight.clone(),
             ground,
             ground_mirror,
             ground_2,
             ground_mirror_2,
             rng,
-            frame_buffer: fbo,
-            texture,
-            view_port: (0.0, 0.0),
-            camera_pos: [0.0; 3],
+            camera: Camera::new(&config.camera),
+            filters,
+            tracking: config.tracking.clone(),
+            presence: HashMap::new(),
+            last_update: clock.start(),
+            output,
+            audio: AudioMapper::new(&config.audio.mappings),
+            dance_phase: 0.0,
         }
     }
 
-    fn update_ground(&mut self, brightness: f32) {
-        let elapsed = self.time.elapsed().as_secs_f32() * 0.5 + 32.0;
+    fn update_ground(&mut self, brightness: f32, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.time).as_secs_f32() * GROUND_SPEED + 32.0;
         let ground_x = elapsed % 32.0 - 16.0;
         let ground_mirror_x = (elapsed - 8.0) % 32.0 - 16.0;
         let ground_2_x = (elapsed - 16.0) % 32.0 - 16.0;
         let ground_mirror_2_x = (elapsed - 24.0) % 32.0 - 16.0;
         
-        self.ground.translation.set_translate(ground_x, -1.0, -2.0);
-        self.ground_mirror.translation.set_translate(ground_mirror_x, -1.0, -2.0);
-        self.ground_2.translation.set_translate(ground_2_x, -1.0, -2.0);
-        self.ground_mirror_2.translation.set_translate(ground_mirror_2_x, -1.0, -2.0);
+        self.ground.translation.set_translate(ground_x, GROUND_HEIGHT, -2.0);
+        self.ground_mirror.translation.set_translate(ground_mirror_x, GROUND_HEIGHT, -2.0);
+        self.ground_2.translation.set_translate(ground_2_x, GROUND_HEIGHT, -2.0);
+        self.ground_mirror_2.translation.set_translate(ground_mirror_2_x, GROUND_HEIGHT, -2.0);
 
         self.ground.color = Color::from_gray(brightness, 1.0);
         self.ground_mirror.color = Color::from_gray(brightness, 1.0);
@@ -137,161 +162,336 @@ impl Scene {
         self.ground_mirror_2.color = Color::from_gray(brightness, 1.0);
     }
 
-    fn update_dandelion(dandelion: &mut DandelionSeed, rng: &mut OsRng, body_pos: [f32; 3], head_pos: [f32; 3], color: Color, drift_strength: f32) {
-        let alpha_y = 0.01;
-        dandelion.theta_delta = alpha_y * (2.0 * rng.gen::<f32>() - 1.0) + (1.0 - alpha_y) * dandelion.theta_delta;
-        dandelion.theta += dandelion.theta_delta;
+    /// Moves a seed `follow` of the way towards the steering target, pointing along its
+    /// aim. Every seed is blown about by the dancers' gusts; as the steering weight drops
+    /// the seed lets go of its body for the whole wind and is drawn to `wind.settle()`,
+    /// where it comes to rest once fully let go.
+    fn update_dandelion(dandelion: &mut DandelionSeed, rng: &mut ChaCha8Rng, wind: &WindField, steering: Steering, follow: f32, dt: f32) {
+        let body_pos = steering.target;
+        let head_pos = [0, 1, 2].map(|i| steering.target[i] + steering.aim[i]);
+        let drift_strength = 1.0 - steering.weight;
+        // the spin about Y wanders randomly, keeping the same spread at any frame rate
+        let decay = (-dt / SPIN_TIME).exp();
+        let noise = 3f32.sqrt() * (2.0 * rng.gen::<f32>() - 1.0);
+        dandelion.theta_delta = decay * dandelion.theta_delta + SPIN_SPEED * (1.0 - decay * decay).sqrt() * noise;
+        dandelion.theta += dandelion.theta_delta * dt;
         let mut theta = AffineMatrix::new();
         theta.set_rotate_y(dandelion.theta);
 
-        let mut drift_position = AffineMatrix::new();
-        drift_position.set_translate(10.0, -1.0, -2.0);
-
-        let alpha = 0.1;
         let mut translate = AffineMatrix::new();
         translate.set_translate(body_pos[0], body_pos[1], body_pos[2]);
-        dandelion.translation.combine(translate, alpha);
-        dandelion.translation.combine(drift_position, drift_strength);
+        dandelion.translation.combine(translate, follow * (1.0 - drift_strength));
+        // a drifting seed is drawn to where drift seeds settle, so it stays in the scene
+        let [x, y, z] = wind.settle();
+        let mut settle = AffineMatrix::new();
+        settle.set_translate(x, y, z);
+        dandelion.translation.combine(settle, blend_factor(drift_strength, dt));
+        let position = dandelion.get_position();
+        let gust = wind.gust(position);
+        let ambient = wind.ambient(position);
+        dandelion.translation.translate(
+            (gust[0] + drift_strength * ambient[0]) * dt,
+            (gust[1] + drift_strength * ambient[1]) * dt,
+            (gust[2] + drift_strength * ambient[2]) * dt,
+        );
 
         let mut rotation = AffineMatrix::new();
         rotation.rotate_towards(theta.multiply_3d(body_pos), theta.multiply_3d(head_pos));
 
-        dandelion.rotation.combine(rotation * theta, alpha);
-        dandelion.color = color;
+        dandelion.rotation.combine(rotation * theta, follow);
     }
 
-    fn dist(left: [f32; 3], right: [f32; 3]) -> f32 {
-        ((left[0] - right[0]).powi(2) + (left[1] - right[1]).powi(2) + (left[2] - right[2]).powi(2)).sqrt()
+    /// Filtered position of a joint. While the filters have no estimate the joint stays
+    /// where it was last seen.
+    fn joint_position(&self, body: usize, joint: Joint) -> [f32; 3] {
+        self.filters.position(body, joint)
+            .or_else(|| self.filters.last_known(body, joint))
+            .unwrap_or([0.0; 3])
     }
 
-    fn check_dandelions(&mut self) {
-        let d1_pos = self.dandelion_seed1.get_position();
-        let d2_pos = self.dandelion_seed2.get_position();
-
-        let body1_pos = *BODY1_BASE_SPINE.lock();
-        let body2_pos = *BODY2_BASE_SPINE.lock();
-
-        let d1_b1_dist = Self::dist(d1_pos, body1_pos);
-        let d2_b1_dist = Self::dist(d2_pos, body1_pos);
-        
-        let d1_b2_dist = Self::dist(d1_pos, body2_pos);
-        let d2_b2_dist = Self::dist(d2_pos, body2_pos);
+    /// Moves a body's presence towards 1 while it is tracked and towards 0 otherwise, taking
+    /// `tracking.transition` seconds for the whole way.
+    fn update_presence(&mut self, body: usize, state: TrackingState, dt: f32) -> f32 {
+        let target = if state == TrackingState::Tracked { 1.0 } else { 0.0 };
+        let step = if self.tracking.transition > 0.0 { dt / self.tracking.transition } else { 1.0 };
+        let presence = self.presence.entry(body).or_insert(0.0);
+        *presence = if target > *presence {
+            (*presence + step).min(target)
+        } else {
+            (*presence - step).max(target)
+        };
+        *presence
+    }
 
-        if d2_b1_dist > d1_b1_dist && d1_b2_dist > d2_b2_dist && (d1_b1_dist > 1.0 || d2_b2_dist > 1.0) {
-            self.swap_dandelions();
+    /// Adjusts where a seed goes and how bright it is according to how present its body is.
+    fn apply_lost_behavior(tracking: &TrackingConfig, presence: f32, body_pos: [f32; 3], target_pos: [f32; 3], color: Color) -> ([f32; 3], [f32; 3], Color) {
+        // eased so the seed leaves and comes back without a jolt
+        let weight = presence * presence * (3.0 - 2.0 * presence);
+        let rest = match tracking.behavior {
+            LostBehavior::Hold => return (body_pos, target_pos, color),
+            LostBehavior::FadeOut => {
+                let mut color = color;
+                color.scale(weight);
+                return (body_pos, target_pos, color);
+            }
+            LostBehavior::DriftAway => tracking.away,
+            LostBehavior::ReturnHome => tracking.home,
+        };
+        let mut moved_body = body_pos;
+        let mut moved_target = target_pos;
+        for i in 0..3 {
+            let offset = (1.0 - weight) * (rest[i] - body_pos[i]);
+            moved_body[i] += offset;
+            moved_target[i] += offset;
         }
+        (moved_body, moved_target, color)
     }
 
-    fn swap_dandelions(&mut self) {
-        std::mem::swap(&mut self.dandelion_seed1, &mut self.dandelion_seed2);
+    fn dist(left: [f32; 3], right: [f32; 3]) -> f32 {
+        ((left[0] - right[0]).powi(2) + (left[1] - right[1]).powi(2) + (left[2] - right[2]).powi(2)).sqrt()
+    }
+
+    /// Hands the following seeds to whichever dancers they are nearest once the dancers
+    /// have crossed: the seeds are matched to the bodies they follow so that the summed
+    /// distance is least, and the new match is taken when some seed has strayed more than
+    /// `swap_distance` from its dancer and every seed that changes dancer ends up closer.
+    /// Returns whether any seed changed dancer.
+    fn check_dandelions(&mut self) -> bool {
+        let followers: Vec<usize> = (0..self.seeds.len()).filter(|&i| self.seeds[i].behavior.body().is_some()).collect();
+        if followers.len() < 2 {
+            return false;
+        }
+        let bodies: Vec<usize> = followers.iter().filter_map(|&i| self.seeds[i].behavior.body()).collect();
+        let body_positions: Vec<[f32; 3]> = bodies.iter().map(|&body| self.joint_position(body, Joint::SpineMid)).collect();
+        let cost: Vec<Vec<f32>> = followers.iter()
+            .map(|&i| {
+                let seed_pos = self.seeds[i].model.get_position();
+                body_positions.iter().map(|&body_pos| Self::dist(seed_pos, body_pos)).collect()
+            })
+            .collect();
+        let assignment = assignment::solve(&cost);
+
+        let strayed = (0..followers.len()).any(|i| cost[i][i] > self.tracking.swap_distance);
+        let changed = (0..followers.len()).any(|i| bodies[assignment[i]] != bodies[i]);
+        let closer = (0..followers.len()).all(|i| bodies[assignment[i]] == bodies[i] || cost[i][assignment[i]] < cost[i][i]);
+        if !(strayed && changed && closer) {
+            return false;
+        }
+        for (&seed, &column) in followers.iter().zip(&assignment) {
+            self.seeds[seed].behavior.set_body(bodies[column]);
+        }
+        true
     }
 
-    pub fn dandelion_dance(&mut self, brightness: f32) {
+    /// Where the `index`th of `count` dancing seeds is at this point of the dance. The seeds
+    /// pair up mirrored about the middle, and the pairs spread evenly around the circle.
+    fn dance_transform(index: usize, count: usize, phase: f32) -> AffineMatrix {
         let initialx_offset = 0.1;
         let initialz_offset = 0.2;
-        let x_angle = PI / 4.0; 
+        let x_angle = PI / 4.0;
         let finaly_offset = -0.2;
         let finalz_offset = -0.5;
-        let time = self.time.elapsed().as_secs_f32();
-        let mut color = Color::from_rgb_float(1.0, 0.85, 0.45);
-        color.scale(brightness);
+        let side = if index % 2 == 0 { 1.0 } else { -1.0 };
+        let pairs = (count + 1) / 2;
+        let offset = 2.0 * PI * (index / 2) as f32 / pairs as f32;
 
         let mut initial_rotation = AffineMatrix::new();
-        initial_rotation.set_rotate_x(x_angle);
+        initial_rotation.set_rotate_x(side * x_angle);
         let mut position = AffineMatrix::new();
-        position.set_translate(initialx_offset, 0.0, initialz_offset);
-        
-        let mut y_rotation = AffineMatrix::new();
-        y_rotation.set_rotate_y(time);
-
-        let mut translation = AffineMatrix::new();
-        translation.set_translate(0.0, finaly_offset, finalz_offset);
-
-        self.dancing_dandelion1.rotation = AffineMatrix::new();
-        self.dancing_dandelion1.translation = initial_rotation * position * y_rotation * translation;
-        self.dancing_dandelion1.color = color;
+        position.set_translate(side * initialx_offset, 0.0, side * initialz_offset);
 
-        let mut initial_rotation = AffineMatrix::new();
-        initial_rotation.set_rotate_x(-x_angle);
-        let mut position = AffineMatrix::new();
-        position.set_translate(-initialx_offset, 0.0, -initialz_offset);
-        
         let mut y_rotation = AffineMatrix::new();
-        y_rotation.set_rotate_y(time);
+        y_rotation.set_rotate_y(phase + offset);
 
         let mut translation = AffineMatrix::new();
         translation.set_translate(0.0, finaly_offset, finalz_offset);
 
-        self.dancing_dandelion2.rotation = AffineMatrix::new();
-        self.dancing_dandelion2.translation = initial_rotation * position * y_rotation * translation;
-        self.dancing_dandelion2.color = color;
+        initial_rotation * position * y_rotation * translation
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        self.seed_renderer.destroy(gl);
+        for ground in [&self.ground, &self.ground_mirror, &self.ground_2, &self.ground_mirror_2] {
+            ground.destroy(gl);
+        }
+    }
 
-        self.dancing_dandelion1.fancy = true;
-        self.dancing_dandelion2.fancy = true;
+    /// Seconds from the start to the last update.
+    pub fn elapsed(&self) -> f32 {
+        self.last_update.saturating_duration_since(self.time).as_secs_f32()
     }
-    
-    pub fn update(&mut self, state: DandelionState) {
-        
- 
-        if state.dancing_brightness > 0.0 {
-            self.dandelion_dance(state.dancing_brightness);
+
+    pub fn trigger(&mut self, trigger: Trigger) {
+        if let Some(seed_head) = self.seed_head.as_mut() {
+            match trigger {
+                Trigger::Release => seed_head.release(),
+                Trigger::Regrow => seed_head.regrow(),
+            }
         }
-        self.update_ground(state.brightness);
-        self.dandelion_seed1.fancy = false;
-        self.dandelion_seed2.fancy = false;
-        let color = Color::from_gray(state.brightness, 1.0);
+    }
+
+    /// Moves everything on to `now`.
+    pub fn update(&mut self, state: DandelionState, now: Instant) {
+        let dt = now.saturating_duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+        let modulation = self.audio.update(&AUDIO.lock(), dt);
+        self.dance_phase += dt * modulation.dance_speed;
+
+        self.update_ground(state.brightness * modulation.ground_brightness, now);
+        let color = Color::from_gray(state.brightness * modulation.seed_glow, 1.0);
+        let mut dance_color = Color::from_rgb_float(1.0, 0.85, 0.45);
+        dance_color.scale(state.dancing_brightness * modulation.seed_glow);
         let affection = state.affection;
-        let body_pos = *BODY1_BASE_SPINE.lock();
-        let head_pos = *BODY1_HEAD.lock();
-        let other_pos = *BODY2_BASE_SPINE.lock();
-        let pos = [
-            other_pos[0] * affection + (1.0 - affection) * head_pos[0],
-            other_pos[1] * affection + (1.0 - affection) * head_pos[1],
-            other_pos[2] * affection + (1.0 - affection) * head_pos[2],
-        ];
-        Self::update_dandelion(&mut self.dandelion_seed1, &mut self.rng, body_pos, pos, color, 0.0);
-        
+        let drift_strength = (state.drift_strength * modulation.drift).clamp(0.0, 1.0);
+        let lost_after = Duration::from_secs_f32(self.tracking.lost_after);
+
+        let mut bodies: Vec<usize> = self.seeds.iter().filter_map(|seed| seed.behavior.body()).collect();
+        bodies.sort_unstable();
+        bodies.dedup();
+        let skeleton = SKELETON.lock();
+        self.filters.update(&skeleton, now);
+        // a body whose seed joints the filters have given up on is lost, even while its
+        // other joints still arrive
+        let states: Vec<TrackingState> = bodies.iter()
+            .map(|&body| match skeleton.tracking_state(body, now, lost_after) {
+                TrackingState::Tracked => [Joint::SpineMid, Joint::Head].iter()
+                    .filter_map(|&joint| self.filters.forgotten_since(body, joint, now))
+                    .min()
+                    .map_or(TrackingState::Tracked, |since| TrackingState::Lost { since }),
+                state => state,
+            })
+            .collect();
+        let mut tracked: Vec<usize> = skeleton.samples().map(|(body, _, _)| body).collect();
+        tracked.sort_unstable();
+        tracked.dedup();
+        tracked.retain(|&body| skeleton.tracking_state(body, now, lost_after) == TrackingState::Tracked);
+        std::mem::drop(skeleton);
+        let filters = &self.filters;
+        let joints: Vec<(usize, Joint, [f32; 3])> = tracked.iter()
+            .flat_map(|&body| Joint::ALL.iter().filter_map(move |&joint| filters.position(body, joint).map(|position| (body, joint, position))))
+            .collect();
+        self.wind.update(&joints, dt);
+        if let Some(seed_head) = self.seed_head.as_mut() {
+            let positions: Vec<[f32; 3]> = joints.iter().map(|&(_, _, position)| position).collect();
+            seed_head.update(&positions, &self.wind, color, dt);
+        }
+        // ordered, so that ties between dancers always go the same way
+        let mut positions = BTreeMap::new();
+        for (&body, &body_state) in bodies.iter().zip(&states) {
+            let presence = self.update_presence(body, body_state, dt);
+            let spine = self.joint_position(body, Joint::SpineMid);
+            let head = self.joint_position(body, Joint::Head);
+            positions.insert(body, (spine, head, presence));
+        }
+
+        if self.tracking.swap_seeds && self.check_dandelions() {
+            if let Some(output) = &self.output {
+                output.send_swap();
+            }
+        }
 
-        let body_pos = *BODY2_BASE_SPINE.lock();
-        let head_pos = *BODY2_HEAD.lock();
-        let other_pos = *BODY1_BASE_SPINE.lock();
-        let pos = [
-            other_pos[0] * affection + (1.0 - affection) * head_pos[0],
-            other_pos[1] * affection + (1.0 - affection) * head_pos[1],
-            other_pos[2] * affection + (1.0 - affection) * head_pos[2],
-        ];
-        Self::update_dandelion(&mut self.dandelion_seed2, &mut self.rng, body_pos, pos, color, state.drift_strength);        
+        // the filters smooth the joints already when they can
+        let follow = if self.filters.smooths() { 1.0 } else { blend_factor(0.1, dt) };
+        let dancers = self.seeds.iter().filter(|seed| seed.behavior == SeedBehavior::Dance).count();
+        let mut dancer = 0;
+        let elapsed = now.saturating_duration_since(self.time).as_secs_f32();
+        for (i, seed) in self.seeds.iter_mut().enumerate() {
+            match seed.behavior {
+                SeedBehavior::Follow { body } | SeedBehavior::Drift { body } => {
+                    let (body_pos, head_pos, presence) = positions[&body];
+                    // leans towards whichever other dancer is nearest
+                    let other_pos = positions.iter()
+                        .filter(|(&other, _)| other != body)
+                        .map(|(_, &(spine, _, _))| spine)
+                        .min_by(|a, b| Self::dist(*a, body_pos).total_cmp(&Self::dist(*b, body_pos)))
+                        .unwrap_or(head_pos);
+                    let pos = [
+                        other_pos[0] * affection + (1.0 - affection) * head_pos[0],
+                        other_pos[1] * affection + (1.0 - affection) * head_pos[1],
+                        other_pos[2] * affection + (1.0 - affection) * head_pos[2],
+                    ];
+                    let (body_pos, pos, seed_color) = Self::apply_lost_behavior(&self.tracking, presence, body_pos, pos, color);
+                    let drift = if matches!(seed.behavior, SeedBehavior::Drift { .. }) { drift_strength } else { 0.0 };
+                    let steering = Steering { target: body_pos, aim: [0, 1, 2].map(|i| pos[i] - body_pos[i]), weight: 1.0 - drift };
+                    seed.follow(&mut self.rng, &self.wind, &self.flight, steering, follow, dt);
+                    seed.model.color = seed_color;
+                    seed.model.fancy = false;
+                }
+                SeedBehavior::Dance => {
+                    if state.dancing_brightness > 0.0 {
+                        seed.model.rotation = AffineMatrix::new();
+                        seed.model.translation = Self::dance_transform(dancer, dancers, self.dance_phase);
+                        seed.model.color = dance_color;
+                        seed.model.fancy = true;
+                    }
+                    dancer += 1;
+                }
+                SeedBehavior::Idle { position } => {
+                    let bob = 0.05 * (0.5 * elapsed + i as f32).sin();
+                    let pos = [position[0], position[1] + bob, position[2]];
+                    let steering = Steering { target: pos, aim: [0.0, IDLE_HEAD_HEIGHT, 0.0], weight: 1.0 };
+                    seed.follow(&mut self.rng, &self.wind, &self.flight, steering, follow, dt);
+                    seed.model.color = color;
+                    seed.model.fancy = false;
+                }
+            }
+        }
+
+        if self.output.as_ref().is_some_and(|output| output.is_due(now)) {
+            let spines: Vec<(usize, [f32; 3])> = positions.iter().map(|(&body, &(spine, _, _))| (body, spine)).collect();
+            let distances = spines.iter().enumerate()
+                .flat_map(|(i, &(body, spine))| spines[i + 1..].iter().map(move |&(other, other_spine)| (body, other, Self::dist(spine, other_spine))))
+                .collect();
+            let report = SceneReport {
+                seeds: self.seeds.iter()
+                    .map(|seed| (seed.model.get_position(), seed.model.get_direction(), seed.model.theta))
+                    .collect(),
+                distances,
+                state,
+            };
+            if let Some(output) = self.output.as_mut() {
+                output.send_report(&report, now);
+            }
+        }
     }
 
-    pub fn paint(&self, gl: &glow::Context, screen_size: (f32, f32), state: DandelionState) {
-        let mut view_matrix = AffineMatrix::new();
-        view_matrix.set_translate(-self.camera_pos[0], -self.camera_pos[1], -self.camera_pos[2]);
+    /// Draws the scene from the camera through `projection`, into the current viewport.
+    pub fn paint(&self, gl: &glow::Context, projection: &AffineMatrix, state: DandelionState) {
+        let view_matrix = self.camera.view_matrix();
 
         unsafe { gl.clear_color(0.0, 0.0, 0.0, 1.0); }
-        self.ground.paint(gl, screen_size, &view_matrix);
-        self.ground_mirror.paint(gl, screen_size, &view_matrix);
-        self.ground_2.paint(gl, screen_size, &view_matrix);
-        self.ground_mirror_2.paint(gl, screen_size, &view_matrix);
-
-        if state.brightness > 0.0 {
-            if self.dandelion_seed1.get_position()[2] < self.dandelion_seed2.get_position()[2] {
-                self.dandelion_seed1.paint(gl, screen_size, &view_matrix);
-                self.dandelion_seed2.paint(gl, screen_size, &view_matrix);
-            } else {
-                self.dandelion_seed2.paint(gl, screen_size, &view_matrix);
-                self.dandelion_seed1.paint(gl, screen_size, &view_matrix);
-            }
+        self.ground.paint(gl, &view_matrix, projection);
+        self.ground_mirror.paint(gl, &view_matrix, projection);
+        self.ground_2.paint(gl, &view_matrix, projection);
+        self.ground_mirror_2.paint(gl, &view_matrix, projection);
+
+        if let Some(seed_head) = self.seed_head.as_ref().filter(|_| state.brightness > 0.0) {
+            self.seed_renderer.paint_stems(gl, &view_matrix, projection, [seed_head.stalk()]);
+            let mut seeds: Vec<&DandelionSeed> = seed_head.seeds().collect();
+            sort_back_to_front(&mut seeds, &view_matrix);
+            self.seed_renderer.paint(gl, &view_matrix, projection, seeds);
         }
-        if state.dancing_brightness > 0.0 {
-            if self.dancing_dandelion1.get_position()[2] < self.dancing_dandelion2.get_position()[2] {
-                self.dancing_dandelion1.paint(gl, screen_size, &view_matrix);
-                self.dancing_dandelion2.paint(gl, screen_size, &view_matrix);
-            } else {
-                self.dancing_dandelion2.paint(gl, screen_size, &view_matrix);
-                self.dancing_dandelion1.paint(gl, screen_size, &view_matrix);
+
+        // the dancing seeds go over the others, and each group is sorted back to front for the
+        // seed renderer's slices
+        for dancing in [false, true] {
+            let level = if dancing { state.dancing_brightness } else { state.brightness };
+            if level <= 0.0 {
+                continue;
             }
+            let mut seeds: Vec<&DandelionSeed> = self.seeds.iter()
+                .filter(|seed| (seed.behavior == SeedBehavior::Dance) == dancing)
+                .map(|seed| &seed.model)
+                .collect();
+            sort_back_to_front(&mut seeds, &view_matrix);
+            self.seed_renderer.paint(gl, &view_matrix, projection, seeds);
         }
-        
-        
     }
-}
\ No newline at end of file
+}
+
+/// Sorts seeds by how far they are along the camera's view, the farthest first: camera
+/// space looks down -z.
+fn sort_back_to_front(seeds: &mut [&DandelionSeed], view_matrix: &AffineMatrix) {
+    let depth = |seed: &DandelionSeed| view_matrix.transform_point(seed.get_position())[2];
+    seeds.sort_by(|a, b| depth(a).total_cmp(&depth(b)));
+}
@@ -0,0 +1,211 @@
+use rand_chacha::ChaCha8Rng;
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::affine_matrix::AffineMatrix;
+use crate::color::Color;
+use crate::config::{FlightConfig, SeedHeadConfig};
+use crate::dandelion::DandelionSeed;
+use crate::flight::Flight;
+use crate::scene::{GROUND_EXTENT, GROUND_HEIGHT, GROUND_SPEED};
+use crate::vector::{add, distance, normalize, scale, sub};
+use crate::wind::WindField;
+
+/// Length of a seed along its stem, in model units, see the extents in `dandelion.vs`.
+const SEED_LENGTH: f32 = 13.2;
+/// Seconds over which the tumble of a flying seed forgets which way it was tipping.
+const TUMBLE_TIME: f32 = 0.5;
+/// Speed a seed pushed off by a dancer leaves the head at, in meters per second.
+const PUSH_SPEED: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    /// On the head, letting go after the delay if there is one.
+    Attached { release_in: Option<f32> },
+    Flying,
+    /// Lying on the ground, carried along with it.
+    Settled,
+    /// Carried off the end of the ground.
+    Gone,
+}
+
+struct Particle {
+    seed: DandelionSeed,
+    stage: Stage,
+    /// Which way the seed points out from the head.
+    outward: [f32; 3],
+    velocity: [f32; 3],
+    /// Horizontal tip of the pappus away from upright.
+    wobble: [f32; 2],
+    /// The seed's flight, while it flies under `[flight]`.
+    flight: Option<Flight>,
+}
+
+/// A whole dandelion clock: seeds on a sphere at the top of a stalk, which let go on a cue
+/// or when a dancer's joint passes through them, blow away on the wind and settle on the
+/// ground.
+pub struct SeedHead {
+    config: SeedHeadConfig,
+    /// Flies the released seeds as rigid bodies when set.
+    flight: Option<FlightConfig>,
+    particles: Vec<Particle>,
+    stalk: DandelionSeed,
+    rng: ChaCha8Rng,
+}
+
+impl SeedHead {
+    pub fn new(config: SeedHeadConfig, flight: Option<FlightConfig>, rng: ChaCha8Rng) -> Self {
+        let mut stalk = DandelionSeed::new();
+        let [x, y, z] = config.position;
+        stalk.translation.set_translate(x, GROUND_HEIGHT, z);
+        // the stem of the seed mesh stretched from the ground up to the head
+        let width = config.seed_size * 2.0;
+        stalk.scale.set_scale(width, (y - GROUND_HEIGHT).max(0.0) / SEED_LENGTH, width);
+        let mut head = Self {
+            config,
+            flight,
+            particles: Vec::new(),
+            stalk,
+            rng,
+        };
+        head.regrow();
+        head
+    }
+
+    /// Puts every seed back on the head.
+    pub fn regrow(&mut self) {
+        let count = self.config.seeds;
+        // spread evenly over the sphere, leaving out the bottom where the stalk joins
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        self.particles = (0..count)
+            .map(|i| {
+                let y = 1.0 - 1.8 * (i as f32 + 0.5) / count as f32;
+                let r = (1.0 - y * y).sqrt();
+                let angle = i as f32 * golden_angle;
+                let outward = [r * angle.cos(), y, r * angle.sin()];
+                let mut seed = DandelionSeed::new();
+                let size = self.config.seed_size;
+                seed.scale.set_scale(size, size, size);
+                Particle {
+                    seed,
+                    stage: Stage::Attached { release_in: None },
+                    outward,
+                    velocity: [0.0; 3],
+                    wobble: [0.0; 2],
+                    flight: None,
+                }
+            })
+            .collect();
+        for particle in self.particles.iter_mut() {
+            let base = add(self.config.position, scale(particle.outward, self.config.radius));
+            particle.seed.translation.set_translate(base[0], base[1], base[2]);
+            particle.seed.rotation = AffineMatrix::pointing(particle.outward, 0.0);
+        }
+    }
+
+    /// Lets go of every seed still on the head, one after another over `release_spread`
+    /// seconds.
+    pub fn release(&mut self) {
+        for particle in self.particles.iter_mut() {
+            if let Stage::Attached { release_in: None } = particle.stage {
+                let delay = self.rng.gen::<f32>() * self.config.release_spread;
+                particle.stage = Stage::Attached { release_in: Some(delay) };
+            }
+        }
+    }
+
+    /// Moves the seeds on by `dt` seconds. Seeds within `release_distance` of any of
+    /// `joints` are pushed off the head, and flying seeds are carried by `wind`.
+    pub fn update(&mut self, joints: &[[f32; 3]], wind: &WindField, color: Color, dt: f32) {
+        self.stalk.color = color;
+        let config = &self.config;
+        for particle in self.particles.iter_mut() {
+            particle.seed.color = color;
+            match particle.stage {
+                Stage::Attached { release_in } => {
+                    let base = particle.seed.get_position();
+                    let touched = joints.iter().any(|&joint| distance(joint, base) < config.release_distance);
+                    let due = release_in.map(|delay| delay - dt);
+                    if touched || due.is_some_and(|delay| delay <= 0.0) {
+                        particle.stage = Stage::Flying;
+                        particle.velocity = scale(particle.outward, if touched { PUSH_SPEED } else { 0.1 * PUSH_SPEED });
+                        if self.flight.is_some() {
+                            let mut flight = Flight::new(base, particle.outward);
+                            flight.velocity = particle.velocity;
+                            particle.flight = Some(flight);
+                        }
+                    } else {
+                        particle.stage = Stage::Attached { release_in: due };
+                    }
+                }
+                Stage::Flying => match (particle.flight.as_mut(), self.flight.as_ref()) {
+                    (Some(flight), Some(flight_config)) => {
+                        flight.advance(flight_config, wind, None, dt);
+                        particle.velocity = flight.velocity;
+                        particle.seed.theta = flight.spin;
+                        if flight.position[1] <= GROUND_HEIGHT {
+                            let position = flight.position;
+                            Self::settle(particle, position);
+                        } else {
+                            let [x, y, z] = flight.position;
+                            particle.seed.translation.set_translate(x, y, z);
+                            particle.seed.rotation = flight.rotation();
+                        }
+                    }
+                    _ => Self::fly(particle, config, wind, &mut self.rng, dt),
+                },
+                Stage::Settled => {
+                    particle.seed.translation.translate(GROUND_SPEED * dt, 0.0, 0.0);
+                    if particle.seed.get_position()[0] > GROUND_EXTENT {
+                        particle.stage = Stage::Gone;
+                    }
+                }
+                Stage::Gone => {}
+            }
+        }
+    }
+
+    /// Drag pulls a flying seed towards moving with the wind; in still air it sinks at
+    /// `sink_speed`, and wind across the pappus lifts it.
+    fn fly(particle: &mut Particle, config: &SeedHeadConfig, wind: &WindField, rng: &mut ChaCha8Rng, dt: f32) {
+        let air = sub(wind.sample(particle.seed.get_position()), particle.velocity);
+        let across = (air[0] * air[0] + air[2] * air[2]).sqrt();
+        let mut acceleration = scale(air, config.drag);
+        acceleration[1] += config.lift * across - config.sink_speed * config.drag;
+        particle.velocity = add(particle.velocity, scale(acceleration, dt));
+        let position = add(particle.seed.get_position(), scale(particle.velocity, dt));
+
+        let decay = (-dt / TUMBLE_TIME).exp();
+        let spread = config.tumble * (1.0 - decay * decay).sqrt();
+        for wobble in particle.wobble.iter_mut() {
+            *wobble = decay * *wobble + spread * (2.0 * rng.gen::<f32>() - 1.0);
+        }
+        particle.seed.theta += config.tumble * 2.0 * PI * dt;
+
+        if position[1] <= GROUND_HEIGHT {
+            Self::settle(particle, position);
+            return;
+        }
+        particle.seed.translation.set_translate(position[0], position[1], position[2]);
+        // the pappus rides upright, tipping into the air flowing past it
+        let up = [particle.wobble[0] + 0.5 * air[0], 1.0, particle.wobble[1] + 0.5 * air[2]];
+        particle.seed.rotation = AffineMatrix::pointing(normalize(up).unwrap_or([0.0, 1.0, 0.0]), particle.seed.theta);
+    }
+
+    /// Lays a seed that has come down at `position` on the ground, the way it was going.
+    fn settle(particle: &mut Particle, position: [f32; 3]) {
+        particle.stage = Stage::Settled;
+        particle.flight = None;
+        particle.seed.translation.set_translate(position[0], GROUND_HEIGHT, position[2]);
+        let lying = normalize([particle.velocity[0], 0.0, particle.velocity[2]]).unwrap_or([1.0, 0.0, 0.0]);
+        particle.seed.rotation = AffineMatrix::pointing(lying, particle.seed.theta);
+    }
+
+    pub fn stalk(&self) -> &DandelionSeed {
+        &self.stalk
+    }
+
+    pub fn seeds(&self) -> impl Iterator<Item = &DandelionSeed> {
+        self.particles.iter().filter(|particle| particle.stage != Stage::Gone).map(|particle| &particle.seed)
+    }
+}
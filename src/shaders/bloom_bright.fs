@@ -0,0 +1,15 @@
+#version 430
+out vec4 fragColor;
+
+in vec2 uv;
+
+uniform sampler2D image;
+uniform float threshold;
+
+void main() {
+    vec3 color = texture(image, uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    // keeps only what rises above the threshold, with the same hue
+    color *= max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+    fragColor = vec4(color, 1.0);
+}
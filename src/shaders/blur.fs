@@ -0,0 +1,19 @@
+#version 430
+out vec4 fragColor;
+
+in vec2 uv;
+
+uniform sampler2D image;
+// one texel along the blur, scaled by the radius
+uniform vec2 step_size;
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(image, uv).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        color += texture(image, uv + step_size * i).rgb * weights[i];
+        color += texture(image, uv - step_size * i).rgb * weights[i];
+    }
+    fragColor = vec4(color, 1.0);
+}
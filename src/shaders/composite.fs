@@ -0,0 +1,43 @@
+#version 430
+out vec4 fragColor;
+
+in vec2 uv;
+
+uniform sampler2D image;
+uniform sampler2D bloom;
+// each effect is off at 0
+uniform float bloom_intensity;
+uniform float exposure;
+uniform bool tonemap;
+uniform float vignette;
+uniform float vignette_softness;
+uniform float grain;
+uniform float time;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+float hash(vec2 p) {
+    p = fract(p * vec2(123.34, 456.21));
+    p += dot(p, p + 45.32);
+    return fract(p.x * p.y);
+}
+
+void main() {
+    vec3 color = texture(image, uv).rgb;
+    // the bloom is left unwritten while it is off
+    if (bloom_intensity > 0.0) {
+        color += bloom_intensity * texture(bloom, uv).rgb;
+    }
+    if (tonemap) {
+        color = aces(color * exposure);
+    }
+    // 0 in the middle, 1 in the corners
+    float edge = length(uv - 0.5) * 1.41421356;
+    color *= 1.0 - vignette * smoothstep(1.0 - vignette_softness, 1.0, edge);
+    // new noise every frame
+    color += grain * (hash(gl_FragCoord.xy + floor(time * 60.0) * vec2(17.0, 59.0)) - 0.5);
+    fragColor = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
@@ -1,7 +1,7 @@
 #version 430
 out vec4 fragColor;
 
-uniform vec4 color;
+flat in vec4 color;
 
 in vec3 normal_interpolated;
 in vec3 vertex_position;
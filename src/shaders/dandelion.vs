@@ -4,33 +4,23 @@
 
 in vec3 position;
 in vec3 normal;
+// per seed: translation * rotation * scale, and its color
+in mat4 model;
+in vec4 instance_color;
 
-uniform vec2 screen_size;
-uniform mat4 translation;
-uniform mat4 rotation;
-uniform mat4 scale;
 uniform mat4 view_matrix;
+// from the camera, see camera.rs
+uniform mat4 projection;
 
 out vec3 normal_interpolated;
+flat out vec4 color;
 out vec3 vertex_position;
 
 void main() {
-    float r = screen_size.x / screen_size.y;
-    float t = 1.0;
-    float n = 0.1;
-    float f = 100.0;
-    float fov = 3.1415 / 3;
-    float tan_fov = tan(fov / 2.0);
-    // perspective transformation
-    mat4 perspective_matrix = mat4(
-        1.0 / (r * tan_fov), 0.0, 0.0, 0.0,
-        0.0, 1.0 / tan_fov, 0.0, 0.0,
-        0.0, 0.0, -(f + n) / (f - n), -1.0,
-        0.0, 0.0, -2.0 * f * n / (f - n), 0.0
-    );
-    mat4 M = view_matrix * translation * rotation * scale;
+    mat4 M = view_matrix * model;
     vec4 camera_position = M * vec4(position, 1.0);
-    gl_Position = perspective_matrix * camera_position;
+    gl_Position = projection * camera_position;
+    color = instance_color;
     
     normal_interpolated = mat3(transpose(inverse(M))) * normal;
     vertex_position = camera_position.xyz;
@@ -2,7 +2,7 @@
 out vec4 fragColor;
 
 uniform float brightness;
-uniform vec4 color;
+flat in vec4 color;
 
 in vec3 normal_interpolated;
 in vec3 vertex_position;
@@ -0,0 +1,27 @@
+#version 430
+out vec4 fragColor;
+
+// x, y, width, height of the output in window pixels, from the bottom left
+uniform vec4 viewport;
+// ramp widths at the left, right, top and bottom edges, as fractions of the output
+uniform vec4 edges;
+uniform float curve;
+uniform float gamma;
+
+// 0 at the outer edge of a ramp rising to 1 at its inner edge, `width` wide
+float ramp(float distance, float width) {
+    if (width <= 0.0) {
+        return 1.0;
+    }
+    float t = clamp(distance / width, 0.0, 1.0);
+    // symmetric about the middle, so two overlapping ramps add up to one
+    float light = t < 0.5 ? 0.5 * pow(2.0 * t, curve) : 1.0 - 0.5 * pow(2.0 * (1.0 - t), curve);
+    return pow(light, 1.0 / gamma);
+}
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - viewport.xy) / viewport.zw;
+    float level = ramp(uv.x, edges.x) * ramp(1.0 - uv.x, edges.y) * ramp(1.0 - uv.y, edges.z) * ramp(uv.y, edges.w);
+    // premultiplied black, so the image underneath is scaled by `level`
+    fragColor = vec4(0.0, 0.0, 0.0, 1.0 - level);
+}
@@ -0,0 +1,11 @@
+#version 430
+out vec4 fragColor;
+
+in vec2 uv;
+
+// the output's image, drawn offscreen
+uniform sampler2D image;
+
+void main() {
+    fragColor = vec4(texture(image, uv).rgb, 1.0);
+}
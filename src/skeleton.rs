@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const NUM_JOINTS: usize = 25;
+
+/// The 25 joints tracked by the Kinect v2, in the sensor's own `JointType` order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Joint {
+    SpineBase,
+    SpineMid,
+    Neck,
+    Head,
+    ShoulderLeft,
+    ElbowLeft,
+    WristLeft,
+    HandLeft,
+    ShoulderRight,
+    ElbowRight,
+    WristRight,
+    HandRight,
+    HipLeft,
+    KneeLeft,
+    AnkleLeft,
+    FootLeft,
+    HipRight,
+    KneeRight,
+    AnkleRight,
+    FootRight,
+    SpineShoulder,
+    HandTipLeft,
+    ThumbLeft,
+    HandTipRight,
+    ThumbRight,
+}
+
+impl Joint {
+    pub const ALL: [Joint; NUM_JOINTS] = [
+        Joint::SpineBase,
+        Joint::SpineMid,
+        Joint::Neck,
+        Joint::Head,
+        Joint::ShoulderLeft,
+        Joint::ElbowLeft,
+        Joint::WristLeft,
+        Joint::HandLeft,
+        Joint::ShoulderRight,
+        Joint::ElbowRight,
+        Joint::WristRight,
+        Joint::HandRight,
+        Joint::HipLeft,
+        Joint::KneeLeft,
+        Joint::AnkleLeft,
+        Joint::FootLeft,
+        Joint::HipRight,
+        Joint::KneeRight,
+        Joint::AnkleRight,
+        Joint::FootRight,
+        Joint::SpineShoulder,
+        Joint::HandTipLeft,
+        Joint::ThumbLeft,
+        Joint::HandTipRight,
+        Joint::ThumbRight,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The name used by the Kinect SDK and the Max `r /joint/bodyN/<Joint>` receivers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Joint::SpineBase => "SpineBase",
+            Joint::SpineMid => "SpineMid",
+            Joint::Neck => "Neck",
+            Joint::Head => "Head",
+            Joint::ShoulderLeft => "ShoulderLeft",
+            Joint::ElbowLeft => "ElbowLeft",
+            Joint::WristLeft => "WristLeft",
+            Joint::HandLeft => "HandLeft",
+            Joint::ShoulderRight => "ShoulderRight",
+            Joint::ElbowRight => "ElbowRight",
+            Joint::WristRight => "WristRight",
+            Joint::HandRight => "HandRight",
+            Joint::HipLeft => "HipLeft",
+            Joint::KneeLeft => "KneeLeft",
+            Joint::AnkleLeft => "AnkleLeft",
+            Joint::FootLeft => "FootLeft",
+            Joint::HipRight => "HipRight",
+            Joint::KneeRight => "KneeRight",
+            Joint::AnkleRight => "AnkleRight",
+            Joint::FootRight => "FootRight",
+            Joint::SpineShoulder => "SpineShoulder",
+            Joint::HandTipLeft => "HandTipLeft",
+            Joint::ThumbLeft => "ThumbLeft",
+            Joint::HandTipRight => "HandTipRight",
+            Joint::ThumbRight => "ThumbRight",
+        }
+    }
+
+    /// Accepts both the SDK spelling (`SpineMid`) and the snake case one used by the
+    /// old Max patch (`spine_mid`).
+    pub fn from_name(name: &str) -> Option<Joint> {
+        let normalized = name.replace('_', "").to_lowercase();
+        Self::ALL.iter().copied().find(|joint| joint.name().to_lowercase() == normalized)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct JointSample {
+    pub position: [f32; 3],
+    pub time: Instant,
+}
+
+/// A new position for one joint, as it arrives from a tracker.
+#[derive(Debug, Clone, Copy)]
+pub struct JointUpdate {
+    pub body: usize,
+    pub joint: Joint,
+    pub position: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Body {
+    joints: [Option<JointSample>; NUM_JOINTS],
+}
+
+impl Body {
+    pub fn get(&self, joint: Joint) -> Option<JointSample> {
+        self.joints[joint.index()]
+    }
+
+    pub fn set(&mut self, joint: Joint, sample: JointSample) {
+        self.joints[joint.index()] = Some(sample);
+    }
+
+    /// Time of the most recent sample of any joint.
+    pub fn last_update(&self) -> Option<Instant> {
+        self.joints.iter().flatten().map(|sample| sample.time).max()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingState {
+    NeverSeen,
+    Tracked,
+    /// No joint of the body has been received since `since`.
+    Lost { since: Instant },
+}
+
+/// Latest known position of every joint of every body, keyed by the body number used
+/// in the OSC addresses (`/joint/body1/...` is body 1).
+#[derive(Default)]
+pub struct Skeleton {
+    bodies: HashMap<usize, Body>,
+}
+
+impl Skeleton {
+    pub fn new() -> Self {
+        Self {
+            bodies: HashMap::new(),
+        }
+    }
+
+    pub fn set_joint(&mut self, body: usize, joint: Joint, position: [f32; 3], time: Instant) {
+        self.bodies
+            .entry(body)
+            .or_default()
+            .set(joint, JointSample { position, time });
+    }
+
+    pub fn joint(&self, body: usize, joint: Joint) -> Option<JointSample> {
+        self.bodies.get(&body).and_then(|b| b.get(joint))
+    }
+
+    /// Applies a whole frame of updates, all stamped with `time`.
+    pub fn apply(&mut self, updates: &[JointUpdate], time: Instant) {
+        for update in updates {
+            self.set_joint(update.body, update.joint, update.position, time);
+        }
+    }
+
+    /// A body is lost once none of its joints has been received for `lost_after`.
+    pub fn tracking_state(&self, body: usize, now: Instant, lost_after: Duration) -> TrackingState {
+        match self.bodies.get(&body).and_then(Body::last_update) {
+            None => TrackingState::NeverSeen,
+            Some(last) if now.saturating_duration_since(last) > lost_after => TrackingState::Lost { since: last },
+            Some(_) => TrackingState::Tracked,
+        }
+    }
+
+    /// Every joint sample received so far, as `(body, joint, sample)`.
+    pub fn samples(&self) -> impl Iterator<Item = (usize, Joint, JointSample)> + '_ {
+        self.bodies.iter().flat_map(|(body, b)| {
+            Joint::ALL.iter().filter_map(move |joint| b.get(*joint).map(|sample| (*body, *joint, sample)))
+        })
+    }
+
+    /// Position of a joint, or the origin if it has never been received.
+    pub fn position(&self, body: usize, joint: Joint) -> [f32; 3] {
+        self.joint(body, joint).map(|sample| sample.position).unwrap_or([0.0; 3])
+    }
+}
@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use std::time::Instant;
+
+/// The frame rate the per-frame smoothing constants were tuned at.
+const REFERENCE_RATE: f32 = 60.0;
+
+/// Converts a fraction blended in once per frame at 60 Hz into the fraction to blend in
+/// after `dt` seconds, so exponential smoothing settles just as fast at any frame rate.
+pub fn blend_factor(per_frame: f32, dt: f32) -> f32 {
+    1.0 - (1.0 - per_frame.clamp(0.0, 1.0)).powf(dt * REFERENCE_RATE)
+}
+
+/// The shape of a fade between two values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Curve {
+    /// Maps the fraction of the fade that has elapsed to the fraction of the way from
+    /// start to end, both in 0..=1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => t * (2.0 - t),
+            Curve::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A value fading from `from` to `to` over `duration` seconds, evaluated against the
+/// clock rather than advanced per frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    from: f32,
+    to: f32,
+    start: Instant,
+    duration: f32,
+    curve: Curve,
+}
+
+impl Transition {
+    pub fn constant(value: f32, now: Instant) -> Self {
+        Self {
+            from: value,
+            to: value,
+            start: now,
+            duration: 0.0,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn value(&self, now: Instant) -> f32 {
+        let progress = self.progress(now);
+        self.from + (self.to - self.from) * self.curve.apply(progress)
+    }
+
+    /// How far through the fade we are, 1.0 once it has finished.
+    pub fn progress(&self, now: Instant) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        (now.saturating_duration_since(self.start).as_secs_f32() / self.duration).min(1.0)
+    }
+
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    /// Starts a new fade from wherever the value is now, so an interrupted fade carries on
+    /// smoothly.
+    pub fn fade_to(&mut self, to: f32, duration: f32, curve: Curve, now: Instant) {
+        *self = Self {
+            from: self.value(now),
+            to,
+            start: now,
+            duration,
+            curve,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CURVES: [Curve; 4] = [Curve::Linear, Curve::EaseIn, Curve::EaseOut, Curve::EaseInOut];
+
+    #[test]
+    fn curves_run_from_zero_to_one() {
+        for curve in CURVES {
+            assert_eq!(curve.apply(0.0), 0.0, "{:?}", curve);
+            assert_eq!(curve.apply(1.0), 1.0, "{:?}", curve);
+            assert_eq!(curve.apply(-1.0), 0.0, "{:?}", curve);
+            assert_eq!(curve.apply(2.0), 1.0, "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_never_turn_back() {
+        for curve in CURVES {
+            let values: Vec<f32> = (0..=100).map(|i| curve.apply(i as f32 / 100.0)).collect();
+            assert!(values.windows(2).all(|pair| pair[1] >= pair[0]), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn transition_reaches_its_target() {
+        let start = Instant::now();
+        let mut transition = Transition::constant(0.0, start);
+        transition.fade_to(2.0, 1.0, Curve::Linear, start);
+        assert_eq!(transition.value(start), 0.0);
+        assert!((transition.value(start + Duration::from_millis(500)) - 1.0).abs() < 1e-3);
+        assert_eq!(transition.value(start + Duration::from_secs(2)), 2.0);
+        assert_eq!(transition.progress(start + Duration::from_secs(2)), 1.0);
+    }
+
+    #[test]
+    fn interrupted_fade_carries_on_from_where_it_was() {
+        let start = Instant::now();
+        let middle = start + Duration::from_millis(500);
+        let mut transition = Transition::constant(0.0, start);
+        transition.fade_to(1.0, 1.0, Curve::Linear, start);
+        let reached = transition.value(middle);
+        transition.fade_to(0.0, 1.0, Curve::Linear, middle);
+        assert_eq!(transition.value(middle), reached);
+    }
+
+    #[test]
+    fn blend_factor_is_frame_rate_independent() {
+        let at_60 = 1.0 - (1.0 - blend_factor(0.1, 1.0 / 60.0)).powi(2);
+        assert!((at_60 - blend_factor(0.1, 1.0 / 30.0)).abs() < 1e-5);
+        assert!((blend_factor(0.1, 1.0 / 60.0) - 0.1).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,37 @@
+pub fn add(left: [f32; 3], right: [f32; 3]) -> [f32; 3] {
+    [left[0] + right[0], left[1] + right[1], left[2] + right[2]]
+}
+
+pub fn sub(left: [f32; 3], right: [f32; 3]) -> [f32; 3] {
+    [left[0] - right[0], left[1] - right[1], left[2] - right[2]]
+}
+
+pub fn scale(v: [f32; 3], factor: f32) -> [f32; 3] {
+    v.map(|x| x * factor)
+}
+
+pub fn dot(left: [f32; 3], right: [f32; 3]) -> f32 {
+    left[0] * right[0] + left[1] * right[1] + left[2] * right[2]
+}
+
+pub fn cross(left: [f32; 3], right: [f32; 3]) -> [f32; 3] {
+    [
+        left[1] * right[2] - left[2] * right[1],
+        left[2] * right[0] - left[0] * right[2],
+        left[0] * right[1] - left[1] * right[0],
+    ]
+}
+
+pub fn length(v: [f32; 3]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+pub fn distance(left: [f32; 3], right: [f32; 3]) -> f32 {
+    length(sub(left, right))
+}
+
+/// `v` scaled to length 1, or `None` if it is too short to have a direction.
+pub fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let length = length(v);
+    (length > 1e-6).then(|| scale(v, 1.0 / length))
+}
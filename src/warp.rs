@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How one output's image is bent onto the surface. The image is first shaped by a Bezier
+/// surface over a grid of control points, then the whole of it is corner pinned, so the
+/// corners fix keystone and the grid takes out curves in the surface. Positions are
+/// fractions of the output from its top left.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Warp {
+    /// Where the image's top left, top right, bottom right and bottom left corners land.
+    pub corners: [[f32; 2]; 4],
+    /// Control points in each row.
+    pub columns: usize,
+    /// Control points row by row from the top, before the corner pin.
+    pub points: Vec<[f32; 2]>,
+}
+
+/// What a warp file holds: one warp per output, in the order of `[[outputs]]`.
+#[derive(Debug, Serialize, Deserialize)]
+struct WarpFile {
+    outputs: Vec<Warp>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<Warp>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read warp {}: {}", path.display(), e))?;
+    let file: WarpFile = toml::from_str(&text)
+        .map_err(|e| format!("Cannot parse warp {}: {}", path.display(), e))?;
+    for (i, warp) in file.outputs.iter().enumerate() {
+        if warp.columns < 2 || warp.points.len() % warp.columns != 0 || warp.points.len() / warp.columns < 2 {
+            return Err(format!("Cannot use warp {}: output {} needs at least 2 rows of `columns` points", path.display(), i + 1).into());
+        }
+    }
+    Ok(file.outputs)
+}
+
+pub fn save(path: &Path, warps: &[Warp]) -> Result<(), Box<dyn std::error::Error>> {
+    let text = toml::to_string(&WarpFile { outputs: warps.to_vec() })?;
+    std::fs::write(path, text)
+        .map_err(|e| format!("Cannot write warp {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+impl Warp {
+    /// A warp that leaves the image as it is, with a grid of `columns` by `rows` points.
+    pub fn new(columns: usize, rows: usize) -> Self {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        let points = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| [column as f32 / (columns - 1) as f32, row as f32 / (rows - 1) as f32]))
+            .collect();
+        Self {
+            corners: [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            columns,
+            points,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.points.len() / self.columns
+    }
+
+    /// Where the point `uv` of the image, as fractions from its top left, lands.
+    pub fn map(&self, uv: [f32; 2]) -> [f32; 2] {
+        self.pin(self.bend(uv))
+    }
+
+    /// The Bezier surface over the control points.
+    fn bend(&self, [u, v]: [f32; 2]) -> [f32; 2] {
+        let across = bernstein(self.columns - 1, u);
+        let down = bernstein(self.rows() - 1, v);
+        let mut point = [0.0; 2];
+        for (row, weight_row) in down.iter().enumerate() {
+            for (column, weight_column) in across.iter().enumerate() {
+                let control = self.points[row * self.columns + column];
+                point[0] += weight_row * weight_column * control[0];
+                point[1] += weight_row * weight_column * control[1];
+            }
+        }
+        point
+    }
+
+    /// Carries a point of the unit square onto the quad between the corners.
+    pub fn pin(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let h = self.homography();
+        let w = h[2][0] * x + h[2][1] * y + h[2][2];
+        [(h[0][0] * x + h[0][1] * y + h[0][2]) / w, (h[1][0] * x + h[1][1] * y + h[1][2]) / w]
+    }
+
+    /// Undoes `pin`, if the corners still make a quad.
+    pub fn unpin(&self, [x, y]: [f32; 2]) -> Option<[f32; 2]> {
+        let h = self.homography();
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| h[r0][c0] * h[r1][c1] - h[r0][c1] * h[r1][c0];
+        let inverse = [
+            [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+            [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+            [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+        ];
+        let w = inverse[2][0] * x + inverse[2][1] * y + inverse[2][2];
+        if w.abs() < 1e-6 {
+            return None;
+        }
+        let point = [(inverse[0][0] * x + inverse[0][1] * y + inverse[0][2]) / w, (inverse[1][0] * x + inverse[1][1] * y + inverse[1][2]) / w];
+        point.iter().all(|v| v.is_finite()).then_some(point)
+    }
+
+    /// The projective map from the unit square to the corners, after Heckbert's
+    /// "Fundamentals of Texture Mapping and Image Warping".
+    fn homography(&self) -> [[f32; 3]; 3] {
+        let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = self.corners;
+        let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+        let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+        let denominator = dx1 * dy2 - dx2 * dy1;
+        let (g, h) = if (dx3 == 0.0 && dy3 == 0.0) || denominator == 0.0 {
+            (0.0, 0.0)
+        } else {
+            ((dx3 * dy2 - dx2 * dy3) / denominator, (dx1 * dy3 - dx3 * dy1) / denominator)
+        };
+        [
+            [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+            [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+            [g, h, 1.0],
+        ]
+    }
+
+    /// Triangles covering the warped image in `steps` by `steps` quads: each vertex is its
+    /// position in clip space then its texture coordinate.
+    pub fn mesh(&self, steps: usize) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = Vec::with_capacity((steps + 1) * (steps + 1) * 4);
+        for row in 0..=steps {
+            for column in 0..=steps {
+                let uv = [column as f32 / steps as f32, row as f32 / steps as f32];
+                let [x, y] = self.map(uv);
+                // clip space and textures both have Y up
+                vertices.extend_from_slice(&[2.0 * x - 1.0, 1.0 - 2.0 * y, uv[0], 1.0 - uv[1]]);
+            }
+        }
+        let mut indices = Vec::with_capacity(steps * steps * 6);
+        for row in 0..steps as u32 {
+            for column in 0..steps as u32 {
+                let corner = row * (steps as u32 + 1) + column;
+                let below = corner + steps as u32 + 1;
+                indices.extend_from_slice(&[corner, below, corner + 1, corner + 1, below, below + 1]);
+            }
+        }
+        (vertices, indices)
+    }
+}
+
+/// The Bernstein polynomials of `degree` at `t`.
+fn bernstein(degree: usize, t: f32) -> Vec<f32> {
+    let mut binomial = 1.0;
+    (0..=degree)
+        .map(|i| {
+            let weight = binomial * t.powi(i as i32) * (1.0 - t).powi((degree - i) as i32);
+            binomial = binomial * (degree - i) as f32 / (i + 1) as f32;
+            weight
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT_SQUARE: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+    fn assert_near(a: [f32; 2], b: [f32; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4, "{:?} is not {:?}", a, b);
+    }
+
+    fn keystoned() -> Warp {
+        Warp {
+            corners: [[0.1, 0.05], [0.85, 0.0], [0.95, 0.9], [0.0, 1.0]],
+            ..Warp::new(3, 3)
+        }
+    }
+
+    #[test]
+    fn new_warp_leaves_the_image_alone() {
+        let warp = Warp::new(4, 3);
+        for uv in [[0.0, 0.0], [0.25, 0.75], [0.5, 0.5], [1.0, 0.3]] {
+            assert_near(warp.map(uv), uv);
+        }
+    }
+
+    #[test]
+    fn pin_carries_the_corners_onto_the_corners() {
+        let warp = keystoned();
+        for (corner, target) in UNIT_SQUARE.iter().zip(warp.corners) {
+            assert_near(warp.pin(*corner), target);
+            assert_near(warp.map(*corner), target);
+        }
+    }
+
+    #[test]
+    fn unpin_undoes_pin() {
+        let warp = keystoned();
+        for point in [[0.0, 0.0], [0.3, 0.6], [0.5, 0.5], [0.9, 0.1], [1.0, 1.0]] {
+            assert_near(warp.unpin(warp.pin(point)).unwrap(), point);
+        }
+    }
+
+    #[test]
+    fn unpin_gives_up_on_collapsed_corners() {
+        let warp = Warp { corners: [[0.5, 0.5]; 4], ..Warp::new(2, 2) };
+        assert!(warp.unpin([0.5, 0.5]).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::config::WindConfig;
+use crate::skeleton::Joint;
+use crate::vector::length;
+
+/// Corner of the box the dancers can gust the air in, in meters.
+const GUST_MIN: [f32; 3] = [-4.0, -1.5, -6.0];
+/// Opposite corner of the gust box.
+const GUST_MAX: [f32; 3] = [4.0, 2.5, 1.0];
+/// Joints moving faster than this, in meters per second, are taken to have jumped.
+const MAX_JOINT_SPEED: f32 = 8.0;
+/// Step used to take the curl of the noise, in noise units.
+const CURL_STEP: f32 = 0.01;
+
+/// The air the seeds move through: a steady wind, swirling turbulence from curl noise, and
+/// gusts the dancers stir up as they move, which die away over `gust_decay` seconds.
+pub struct WindField {
+    config: WindConfig,
+    time: f32,
+    cells: [usize; 3],
+    /// Gust velocity at each grid point, x fastest.
+    gusts: Vec<[f32; 3]>,
+    /// Where each joint was last frame.
+    last_joints: HashMap<(usize, Joint), [f32; 3]>,
+}
+
+impl WindField {
+    pub fn new(config: WindConfig) -> Self {
+        let cell_size = config.cell_size.max(0.05);
+        let cells = [0, 1, 2].map(|axis| ((GUST_MAX[axis] - GUST_MIN[axis]) / cell_size).ceil() as usize + 1);
+        Self {
+            config: WindConfig { cell_size, ..config },
+            time: 0.0,
+            cells,
+            gusts: vec![[0.0; 3]; cells[0] * cells[1] * cells[2]],
+            last_joints: HashMap::new(),
+        }
+    }
+
+    /// Moves the field on by `dt` seconds, stirring it with the joints tracked this frame.
+    pub fn update(&mut self, joints: &[(usize, Joint, [f32; 3])], dt: f32) {
+        self.time += dt;
+        let decay = if self.config.gust_decay > 0.0 { (-dt / self.config.gust_decay).exp() } else { 0.0 };
+        for gust in self.gusts.iter_mut() {
+            *gust = gust.map(|v| v * decay);
+        }
+        if dt <= 0.0 {
+            return;
+        }
+
+        let mut seen = HashMap::new();
+        for &(body, joint, position) in joints {
+            if let Some(last) = self.last_joints.get(&(body, joint)) {
+                let velocity = [0, 1, 2].map(|i| (position[i] - last[i]) / dt);
+                if length(velocity) < MAX_JOINT_SPEED {
+                    self.inject(position, velocity, dt);
+                }
+            }
+            seen.insert((body, joint), position);
+        }
+        self.last_joints = seen;
+    }
+
+    /// Blends the gusts around `position` towards `velocity`, most strongly in the middle.
+    fn inject(&mut self, position: [f32; 3], velocity: [f32; 3], dt: f32) {
+        let radius = self.config.gust_radius;
+        if radius <= 0.0 {
+            return;
+        }
+        let target = velocity.map(|v| v * self.config.gust_strength);
+        // reaches the joint's speed in about a tenth of a second right next to it
+        let rate = 1.0 - (-dt / 0.1).exp();
+        let cell_size = self.config.cell_size;
+        let low = [0, 1, 2].map(|axis| (((position[axis] - radius - GUST_MIN[axis]) / cell_size).floor().max(0.0)) as usize);
+        let high = [0, 1, 2].map(|axis| (((position[axis] + radius - GUST_MIN[axis]) / cell_size).ceil().max(0.0) as usize).min(self.cells[axis] - 1));
+        for z in low[2]..=high[2] {
+            for y in low[1]..=high[1] {
+                for x in low[0]..=high[0] {
+                    let point = [x, y, z].map(|i| i as f32 * cell_size);
+                    let offset = [0, 1, 2].map(|axis| GUST_MIN[axis] + point[axis] - position[axis]);
+                    let distance = length(offset);
+                    if distance > radius {
+                        continue;
+                    }
+                    let weight = rate * (-2.0 * (distance / radius).powi(2)).exp();
+                    let index = self.index(x, y, z);
+                    let gust = &mut self.gusts[index];
+                    for axis in 0..3 {
+                        gust[axis] += (target[axis] - gust[axis]) * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The whole wind at `position`, in meters per second.
+    pub fn sample(&self, position: [f32; 3]) -> [f32; 3] {
+        let ambient = self.ambient(position);
+        let gust = self.gust(position);
+        [0, 1, 2].map(|axis| ambient[axis] + gust[axis])
+    }
+
+    /// Where seeds that have let go of their dancers come to rest.
+    pub fn settle(&self) -> [f32; 3] {
+        self.config.settle
+    }
+
+    /// The steady wind and turbulence at `position`, without the dancers' gusts.
+    pub fn ambient(&self, position: [f32; 3]) -> [f32; 3] {
+        let steady = self.config.steady;
+        if self.config.turbulence == 0.0 || self.config.turbulence_scale <= 0.0 {
+            return steady;
+        }
+        let shift = self.time * self.config.turbulence_speed;
+        let p = [0, 1, 2].map(|axis| position[axis] / self.config.turbulence_scale + shift * [0.7, 1.0, 1.3][axis]);
+        let curl = curl_noise(p);
+        [0, 1, 2].map(|axis| steady[axis] + self.config.turbulence * curl[axis])
+    }
+
+    /// The gusts the dancers have stirred up at `position`, interpolated between grid points.
+    pub fn gust(&self, position: [f32; 3]) -> [f32; 3] {
+        let mut base = [0usize; 3];
+        let mut fraction = [0.0f32; 3];
+        for axis in 0..3 {
+            let cell = (position[axis] - GUST_MIN[axis]) / self.config.cell_size;
+            if cell < 0.0 || cell > (self.cells[axis] - 1) as f32 {
+                return [0.0; 3];
+            }
+            base[axis] = (cell.floor() as usize).min(self.cells[axis] - 2);
+            fraction[axis] = cell - base[axis] as f32;
+        }
+        let mut gust = [0.0; 3];
+        for corner in 0..8 {
+            let step = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight: f32 = (0..3).map(|axis| if step[axis] == 1 { fraction[axis] } else { 1.0 - fraction[axis] }).product();
+            let value = self.gusts[self.index(base[0] + step[0], base[1] + step[1], base[2] + step[2])];
+            for axis in 0..3 {
+                gust[axis] += weight * value[axis];
+            }
+        }
+        gust
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.cells[1] + y) * self.cells[0] + x
+    }
+}
+
+/// Divergence free noise, so the turbulence swirls without piling the seeds up anywhere:
+/// the curl of three gradient noise fields.
+fn curl_noise(p: [f32; 3]) -> [f32; 3] {
+    // offsets keep the three fields apart
+    let potential = |p: [f32; 3], field: usize| {
+        let offset = [0.0, 31.4, 77.7][field];
+        gradient_noise([p[0] + offset, p[1] + offset, p[2] + offset])
+    };
+    let derivative = |field: usize, axis: usize| {
+        let mut ahead = p;
+        let mut behind = p;
+        ahead[axis] += CURL_STEP;
+        behind[axis] -= CURL_STEP;
+        (potential(ahead, field) - potential(behind, field)) / (2.0 * CURL_STEP)
+    };
+    [
+        derivative(2, 1) - derivative(1, 2),
+        derivative(0, 2) - derivative(2, 0),
+        derivative(1, 0) - derivative(0, 1),
+    ]
+}
+
+/// Perlin's gradient noise, about -1 to 1.
+fn gradient_noise(p: [f32; 3]) -> f32 {
+    let cell = p.map(|v| v.floor());
+    let local = [0, 1, 2].map(|axis| p[axis] - cell[axis]);
+    let fade = local.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let gradient = gradient(hash(cell[0] as i32 + dx, cell[1] as i32 + dy, cell[2] as i32 + dz));
+        gradient[0] * (local[0] - dx as f32) + gradient[1] * (local[1] - dy as f32) + gradient[2] * (local[2] - dz as f32)
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade[0]);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade[0]);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade[0]);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade[0]);
+    lerp(lerp(x00, x10, fade[1]), lerp(x01, x11, fade[1]), fade[2])
+}
+
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^ (h >> 15)
+}
+
+/// One of the twelve edge directions of a cube.
+fn gradient(hash: u32) -> [f32; 3] {
+    match hash % 12 {
+        0 => [1.0, 1.0, 0.0],
+        1 => [-1.0, 1.0, 0.0],
+        2 => [1.0, -1.0, 0.0],
+        3 => [-1.0, -1.0, 0.0],
+        4 => [1.0, 0.0, 1.0],
+        5 => [-1.0, 0.0, 1.0],
+        6 => [1.0, 0.0, -1.0],
+        7 => [-1.0, 0.0, -1.0],
+        8 => [0.0, 1.0, 1.0],
+        9 => [0.0, -1.0, 1.0],
+        10 => [0.0, 1.0, -1.0],
+        _ => [0.0, -1.0, -1.0],
+    }
+}